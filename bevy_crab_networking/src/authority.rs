@@ -0,0 +1,345 @@
+use crate::{
+    entity_map::replicated_entity,
+    has_client_config, has_server_config,
+    replication::{replicate_component, ReplicationSet, AUTHORITY_COMPONENT},
+    tick::TickSet,
+    ClientDataReadEvent, ClientDataUploader, ConnectionChange, Identify, IntergressType,
+    NetworkEntity, NetworkEntityMap, PlayerIntergressEvent, Recipient, Replicated,
+    ServerConnectionChangeEvent, ServerDataReadEvent, ServerDataUploader, AUTHORITY_IDENTIFIER,
+};
+use bevy::{ecs::event::ManualEventReader, prelude::*};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+type UpdateFn = fn(&mut World, u32, Entity, &[u8]) -> bincode::Result<Option<Vec<u8>>>;
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Authority {
+    #[default]
+    Server,
+    Client(u32),
+}
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalClientId(u32);
+impl LocalClientId {
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+}
+#[derive(Event, Clone, Copy, Debug)]
+pub struct RequestAuthority {
+    pub entity: Entity,
+    pub release: bool,
+}
+#[derive(Event, Clone, Copy, Debug)]
+pub struct AuthorityRequestEvent {
+    pub id: u32,
+    pub entity: Entity,
+    pub release: bool,
+}
+pub trait AuthorityApp {
+    fn client_authority<C: Component + Serialize + DeserializeOwned>(&mut self) -> &mut Self;
+    fn client_authority_validated<C: Component + Serialize + DeserializeOwned>(
+        &mut self,
+        validate: fn(u32, Entity, &C) -> bool,
+    ) -> &mut Self;
+}
+impl AuthorityApp for App {
+    fn client_authority<C: Component + Serialize + DeserializeOwned>(&mut self) -> &mut Self {
+        register_client_authority::<C>(self, None)
+    }
+    fn client_authority_validated<C: Component + Serialize + DeserializeOwned>(
+        &mut self,
+        validate: fn(u32, Entity, &C) -> bool,
+    ) -> &mut Self {
+        register_client_authority::<C>(self, Some(validate))
+    }
+}
+fn register_client_authority<C: Component + Serialize + DeserializeOwned>(
+    app: &mut App,
+    validate: Option<fn(u32, Entity, &C) -> bool>,
+) -> &mut App {
+    app.init_resource::<AuthorityRegistry>();
+    let mut registry = app.world_mut().resource_mut::<AuthorityRegistry>();
+    let index = registry.components.len() as u16;
+    registry.components.push(AuthorityFns {
+        update: update_component::<C>,
+        correct: correct_component::<C>,
+    });
+    app.insert_resource(ClientAuthority::<C> { index, validate })
+        .add_systems(
+            FixedPostUpdate,
+            send_owned_component::<C>
+                .before(TickSet::Flush)
+                .run_if(has_client_config),
+        )
+}
+#[derive(Resource)]
+pub(crate) struct ClientAuthority<C> {
+    index: u16,
+    validate: Option<fn(u32, Entity, &C) -> bool>,
+}
+#[derive(Resource, Default)]
+struct AuthorityRegistry {
+    components: Vec<AuthorityFns>,
+}
+struct AuthorityFns {
+    update: UpdateFn,
+    correct: fn(&mut World, Entity, &[u8]) -> bincode::Result<()>,
+}
+#[derive(Serialize, Deserialize, Debug)]
+enum AuthorityMessage {
+    Welcome {
+        id: u32,
+    },
+    Update {
+        entity: NetworkEntity,
+        index: u16,
+        bytes: Vec<u8>,
+    },
+    Correction {
+        entity: NetworkEntity,
+        index: u16,
+        bytes: Vec<u8>,
+    },
+    Request {
+        entity: NetworkEntity,
+        release: bool,
+    },
+}
+impl Identify for AuthorityMessage {
+    fn get_identifier(&self) -> u32 {
+        AUTHORITY_IDENTIFIER
+    }
+}
+pub(crate) fn is_locally_owned(entity: &EntityWorldMut) -> bool {
+    let Some(local_client_id) = entity.world().get_resource::<LocalClientId>() else {
+        return false;
+    };
+    entity.get::<Authority>() == Some(&Authority::Client(local_client_id.0))
+}
+fn update_component<C: Component + Serialize + DeserializeOwned>(
+    world: &mut World,
+    id: u32,
+    entity: Entity,
+    bytes: &[u8],
+) -> bincode::Result<Option<Vec<u8>>> {
+    let component: C = world.resource::<NetworkEntityMap>().deserialize(bytes)?;
+    let validate = world.resource::<ClientAuthority<C>>().validate;
+    if validate.is_none_or(|validate| validate(id, entity, &component)) {
+        world.entity_mut(entity).insert(component);
+        return Ok(None);
+    }
+    match world.get::<C>(entity) {
+        Some(component) => bincode::serialize(component).map(Some),
+        None => Ok(None),
+    }
+}
+fn correct_component<C: Component + DeserializeOwned>(
+    world: &mut World,
+    entity: Entity,
+    bytes: &[u8],
+) -> bincode::Result<()> {
+    let component: C = world.resource::<NetworkEntityMap>().deserialize(bytes)?;
+    world.entity_mut(entity).insert(component);
+    Ok(())
+}
+pub(crate) struct AuthorityPlugin;
+impl Plugin for AuthorityPlugin {
+    fn build(&self, app: &mut App) {
+        replicate_component::<Authority>(app, AUTHORITY_COMPONENT);
+        app.init_resource::<AuthorityRegistry>()
+            .add_event::<RequestAuthority>()
+            .add_event::<AuthorityRequestEvent>()
+            .add_systems(
+                PreUpdate,
+                (
+                    receive_authority_messages
+                        .after(ReplicationSet::Receive)
+                        .run_if(has_client_config),
+                    send_authority_requests.run_if(has_client_config),
+                    receive_owned_components.run_if(has_server_config),
+                ),
+            );
+    }
+}
+fn send_owned_component<C: Component + Serialize>(
+    owned: Query<(&NetworkEntity, &Authority, Ref<C>), With<Replicated>>,
+    local_client_id: Option<Res<LocalClientId>>,
+    client_authority: Res<ClientAuthority<C>>,
+    client_data_uploader: Option<ResMut<ClientDataUploader>>,
+) {
+    let (Some(local_client_id), Some(mut client_data_uploader)) =
+        (local_client_id, client_data_uploader)
+    else {
+        return;
+    };
+    let network_entity_map = client_data_uploader.network_entity_map.clone();
+    for (network_entity, authority, component) in owned.iter() {
+        if *authority != Authority::Client(local_client_id.0) || !component.is_changed() {
+            continue;
+        }
+        let bytes = match network_entity_map.scope(|| bincode::serialize(component.as_ref())) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                println!("Failed to serialize an owned component: {err:#?}");
+                continue;
+            }
+        };
+        let _ = client_data_uploader.upload(AuthorityMessage::Update {
+            entity: *network_entity,
+            index: client_authority.index,
+            bytes,
+        });
+    }
+}
+fn send_authority_requests(
+    mut request_authority_reader: EventReader<RequestAuthority>,
+    network_entity_map: Res<NetworkEntityMap>,
+    client_data_uploader: Option<ResMut<ClientDataUploader>>,
+) {
+    let Some(mut client_data_uploader) = client_data_uploader else {
+        return;
+    };
+    for request in request_authority_reader.read() {
+        let Some(entity) = network_entity_map.to_network(request.entity) else {
+            continue;
+        };
+        let _ = client_data_uploader.upload(AuthorityMessage::Request {
+            entity,
+            release: request.release,
+        });
+    }
+}
+fn receive_authority_messages(
+    world: &mut World,
+    mut client_data_read_reader: Local<ManualEventReader<ClientDataReadEvent>>,
+    mut server_connection_change_reader: Local<ManualEventReader<ServerConnectionChangeEvent>>,
+) {
+    let disconnected = server_connection_change_reader
+        .read(world.resource::<Events<ServerConnectionChangeEvent>>())
+        .any(|event| matches!(event.connection_change, ConnectionChange::Disconnected));
+    if disconnected {
+        world.remove_resource::<LocalClientId>();
+    }
+    let messages: Vec<AuthorityMessage> = client_data_read_reader
+        .read(world.resource::<Events<ClientDataReadEvent>>())
+        .filter(|event| event.data_packet.identifier == AUTHORITY_IDENTIFIER)
+        .filter_map(|event| bincode::deserialize(&event.data_packet.bytes).ok())
+        .collect();
+    let network_entity_map = world.resource::<NetworkEntityMap>().clone();
+    world.resource_scope(|world, registry: Mut<AuthorityRegistry>| {
+        for message in messages {
+            match message {
+                AuthorityMessage::Welcome { id } => world.insert_resource(LocalClientId(id)),
+                AuthorityMessage::Correction {
+                    entity,
+                    index,
+                    bytes,
+                } => {
+                    let (Some(entity), Some(authority_fns)) = (
+                        network_entity_map.to_local(entity),
+                        registry.components.get(index as usize),
+                    ) else {
+                        continue;
+                    };
+                    if let Err(err) = (authority_fns.correct)(world, entity, &bytes) {
+                        println!("Failed to deserialize a corrected component: {err:#?}");
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+}
+fn receive_owned_components(
+    world: &mut World,
+    mut server_data_read_reader: Local<ManualEventReader<ServerDataReadEvent>>,
+    mut player_intergress_reader: Local<ManualEventReader<PlayerIntergressEvent>>,
+) {
+    let intergresses: Vec<PlayerIntergressEvent> = player_intergress_reader
+        .read(world.resource::<Events<PlayerIntergressEvent>>())
+        .cloned()
+        .collect();
+    for event in intergresses {
+        match event.intergress_type {
+            IntergressType::Joined => {
+                world.resource_mut::<ServerDataUploader>().upload(
+                    AuthorityMessage::Welcome { id: event.id },
+                    Recipient::Single { id: event.id },
+                );
+            }
+            IntergressType::Left => {
+                let mut authorities = world.query::<&mut Authority>();
+                for mut authority in authorities.iter_mut(world) {
+                    if *authority == Authority::Client(event.id) {
+                        *authority = Authority::Server;
+                    }
+                }
+            }
+        }
+    }
+    let messages: Vec<(u32, AuthorityMessage)> = server_data_read_reader
+        .read(world.resource::<Events<ServerDataReadEvent>>())
+        .filter(|event| event.data_packet.identifier == AUTHORITY_IDENTIFIER)
+        .filter_map(
+            |event| match bincode::deserialize(&event.data_packet.bytes) {
+                Ok(message) => Some((event.id, message)),
+                Err(err) => {
+                    println!(
+                        "Failed to deserialize an authority message from id {}: {err:#?}",
+                        event.id
+                    );
+                    None
+                }
+            },
+        )
+        .collect();
+    world.resource_scope(|world, registry: Mut<AuthorityRegistry>| {
+        for (id, message) in messages {
+            match message {
+                AuthorityMessage::Update {
+                    entity: network_entity,
+                    index,
+                    bytes,
+                } => {
+                    let Some(entity) = replicated_entity(world, network_entity) else {
+                        continue;
+                    };
+                    if world.get::<Authority>(entity) != Some(&Authority::Client(id)) {
+                        continue;
+                    }
+                    let Some(authority_fns) = registry.components.get(index as usize) else {
+                        continue;
+                    };
+                    match (authority_fns.update)(world, id, entity, &bytes) {
+                        Ok(Some(bytes)) => {
+                            world.resource_mut::<ServerDataUploader>().upload(
+                                AuthorityMessage::Correction {
+                                    entity: network_entity,
+                                    index,
+                                    bytes,
+                                },
+                                Recipient::Single { id },
+                            );
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            println!(
+                                "Failed to deserialize an owned component from id {id}: {err:#?}"
+                            );
+                        }
+                    }
+                }
+                AuthorityMessage::Request { entity, release } => {
+                    let Some(entity) = replicated_entity(world, entity) else {
+                        continue;
+                    };
+                    world.send_event(AuthorityRequestEvent {
+                        id,
+                        entity,
+                        release,
+                    });
+                }
+                _ => {}
+            }
+        }
+    });
+}
@@ -0,0 +1,200 @@
+use crate::{combine_u8s_into_u32, split_u32_into_u8s, ConnectionError};
+use bytes::Bytes;
+use std::{
+    io::{Error, ErrorKind},
+    sync::Arc,
+};
+pub(crate) const UNCOMPRESSED: u8 = 0;
+pub(crate) const LZ4_COMPRESSED: u8 = 1;
+pub(crate) const ZSTD_COMPRESSED: u8 = 2;
+#[derive(Clone)]
+pub enum Compression {
+    Lz4 {
+        threshold: usize,
+        dictionary: Option<Arc<Vec<u8>>>,
+    },
+    Zstd {
+        threshold: usize,
+        level: i32,
+        dictionary: Option<Arc<Vec<u8>>>,
+    },
+    None,
+}
+impl Compression {
+    fn dictionary(&self) -> Option<&[u8]> {
+        match self {
+            Compression::Lz4 { dictionary, .. } | Compression::Zstd { dictionary, .. } => {
+                dictionary.as_ref().map(|dictionary| dictionary.as_slice())
+            }
+            Compression::None => None,
+        }
+    }
+    pub(crate) fn compress(&self, bytes: &[u8]) -> Option<(u8, Vec<u8>)> {
+        let (flag, compressed) = match self {
+            Compression::Lz4 {
+                threshold,
+                dictionary,
+            } => {
+                if bytes.len() < *threshold {
+                    return None;
+                }
+                let compressed = match dictionary {
+                    Some(dictionary) => lz4_flex::block::compress_with_dict(bytes, dictionary),
+                    None => lz4_flex::block::compress(bytes),
+                };
+                (LZ4_COMPRESSED, compressed)
+            }
+            Compression::Zstd {
+                threshold,
+                level,
+                dictionary,
+            } => {
+                if bytes.len() < *threshold {
+                    return None;
+                }
+                let compressed = match dictionary {
+                    Some(dictionary) => zstd::bulk::Compressor::with_dictionary(*level, dictionary)
+                        .and_then(|mut compressor| compressor.compress(bytes)),
+                    None => zstd::bulk::compress(bytes, *level),
+                };
+                match compressed {
+                    Ok(compressed) => (ZSTD_COMPRESSED, compressed),
+                    Err(err) => {
+                        println!("Compression failed: {err:#?}. Sending the packet uncompressed");
+                        return None;
+                    }
+                }
+            }
+            Compression::None => return None,
+        };
+        if compressed.len() + 4 >= bytes.len() {
+            return None;
+        }
+        let mut framed = split_u32_into_u8s(bytes.len() as u32).to_vec();
+        framed.extend_from_slice(&compressed);
+        Some((flag, framed))
+    }
+    pub(crate) fn decompress(
+        &self,
+        flag: u8,
+        bytes: Bytes,
+        max_size: usize,
+    ) -> Result<Bytes, ConnectionError> {
+        if flag == UNCOMPRESSED {
+            return Ok(bytes);
+        }
+        if bytes.len() < 4 {
+            return Err(ConnectionError::InvalidFrame);
+        }
+        let (size, compressed) = bytes.split_at(4);
+        let size = combine_u8s_into_u32(size.try_into().unwrap()) as usize;
+        if size > max_size {
+            return Err(ConnectionError::FrameTooLarge(size));
+        }
+        let decompressed = match flag {
+            LZ4_COMPRESSED => match self.dictionary() {
+                Some(dictionary) => {
+                    lz4_flex::block::decompress_with_dict(compressed, size, dictionary)
+                }
+                None => lz4_flex::block::decompress(compressed, size),
+            }
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err)),
+            ZSTD_COMPRESSED => match self.dictionary() {
+                Some(dictionary) => zstd::bulk::Decompressor::with_dictionary(dictionary)
+                    .and_then(|mut decompressor| decompressor.decompress(compressed, size)),
+                None => zstd::bulk::decompress(compressed, size),
+            },
+            _ => return Err(ConnectionError::InvalidFrame),
+        };
+        match decompressed {
+            Ok(decompressed) if decompressed.len() == size => Ok(decompressed.into()),
+            Ok(_) => Err(ConnectionError::InvalidFrame),
+            Err(err) => Err(ConnectionError::DecompressionFailed(err)),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn compressions() -> Vec<Compression> {
+        let dictionary = Some(Arc::new(b"position velocity rotation".repeat(8)));
+        vec![
+            Compression::Lz4 {
+                threshold: 16,
+                dictionary: None,
+            },
+            Compression::Lz4 {
+                threshold: 16,
+                dictionary: dictionary.clone(),
+            },
+            Compression::Zstd {
+                threshold: 16,
+                level: 3,
+                dictionary: None,
+            },
+            Compression::Zstd {
+                threshold: 16,
+                level: 3,
+                dictionary,
+            },
+        ]
+    }
+    #[test]
+    fn round_trips() {
+        let bytes = b"position velocity rotation ".repeat(40);
+        for compression in compressions() {
+            let (flag, compressed) = compression.compress(&bytes).unwrap();
+            assert!(compressed.len() < bytes.len());
+            let decompressed = compression
+                .decompress(flag, compressed.into(), bytes.len())
+                .unwrap();
+            assert_eq!(decompressed, bytes);
+        }
+    }
+    #[test]
+    fn skips_small_and_incompressible_packets() {
+        let incompressible: Vec<u8> = (0..64u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect();
+        for compression in compressions() {
+            assert!(compression.compress(&[0; 15]).is_none());
+            assert!(compression.compress(&incompressible).is_none());
+        }
+        assert!(Compression::None.compress(&[0; 1024]).is_none());
+        let bytes = Bytes::from_static(&[1, 2, 3]);
+        let decompressed = Compression::None
+            .decompress(UNCOMPRESSED, bytes.clone(), 0)
+            .unwrap();
+        assert_eq!(decompressed, bytes);
+    }
+    #[test]
+    fn rejects_sizes_over_the_maximum() {
+        let bytes = vec![0; 4096];
+        for compression in compressions() {
+            let (flag, compressed) = compression.compress(&bytes).unwrap();
+            let result = compression.decompress(flag, compressed.into(), 4095);
+            assert!(matches!(result, Err(ConnectionError::FrameTooLarge(4096))));
+        }
+        let mut forged = split_u32_into_u8s(u32::MAX).to_vec();
+        forged.extend_from_slice(&[1, 2, 3, 4]);
+        for flag in [LZ4_COMPRESSED, ZSTD_COMPRESSED] {
+            let result = compressions()[0].decompress(flag, forged.clone().into(), 1024);
+            assert!(matches!(result, Err(ConnectionError::FrameTooLarge(_))));
+        }
+    }
+    #[test]
+    fn rejects_malformed_payloads() {
+        let bytes = vec![0; 4096];
+        for compression in compressions() {
+            let (flag, mut compressed) = compression.compress(&bytes).unwrap();
+            compressed[..4].copy_from_slice(&split_u32_into_u8s(4095));
+            assert!(compression
+                .decompress(flag, compressed.into(), 8192)
+                .is_err());
+            let result = compression.decompress(flag, Bytes::from_static(&[0, 0]), 8192);
+            assert!(matches!(result, Err(ConnectionError::InvalidFrame)));
+            let result = compression.decompress(9, Bytes::from_static(&[0, 0, 0, 1, 0]), 8192);
+            assert!(matches!(result, Err(ConnectionError::InvalidFrame)));
+        }
+    }
+}
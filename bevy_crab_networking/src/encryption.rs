@@ -0,0 +1,185 @@
+use crate::{combine_u8s_into_u32, split_u32_into_u8s, ConnectionError};
+use snow::{params::NoiseParams, Builder, HandshakeState, TransportState};
+use std::{sync::Mutex, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+const NOISE_PATTERN: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+const MAX_NOISE_MESSAGE_SIZE: usize = 65535;
+const TAG_SIZE: usize = 16;
+const MAX_CHUNK_PAYLOAD_SIZE: usize = MAX_NOISE_MESSAGE_SIZE - TAG_SIZE;
+#[derive(Clone)]
+pub enum ServerEncryption {
+    Noise { keypair: EncryptionKeypair },
+    None,
+}
+#[derive(Clone)]
+pub enum ClientEncryption {
+    Noise { pinned_server_key: Option<[u8; 32]> },
+    None,
+}
+#[derive(Clone)]
+pub struct EncryptionKeypair {
+    pub public_key: [u8; 32],
+    pub private_key: [u8; 32],
+}
+impl EncryptionKeypair {
+    pub fn generate() -> EncryptionKeypair {
+        let keypair = Builder::new(noise_params())
+            .generate_keypair()
+            .expect("Failed to generate an encryption keypair");
+        EncryptionKeypair {
+            public_key: keypair.public.try_into().unwrap(),
+            private_key: keypair.private.try_into().unwrap(),
+        }
+    }
+}
+pub struct EncryptedSession {
+    transport: Mutex<TransportState>,
+}
+impl EncryptedSession {
+    pub fn remote_public_key(&self) -> Option<[u8; 32]> {
+        self.transport
+            .lock()
+            .unwrap()
+            .get_remote_static()
+            .and_then(|key| key.try_into().ok())
+    }
+    pub(crate) fn seal(&self, frame: &[u8]) -> Result<Vec<u8>, ConnectionError> {
+        let mut transport = self.transport.lock().unwrap();
+        let mut body = vec![];
+        let mut message = [0u8; MAX_NOISE_MESSAGE_SIZE];
+        for chunk in frame.chunks(MAX_CHUNK_PAYLOAD_SIZE) {
+            let len = transport
+                .write_message(chunk, &mut message)
+                .map_err(ConnectionError::EncryptionErr)?;
+            body.extend_from_slice(&message[..len]);
+        }
+        let mut sealed = split_u32_into_u8s(body.len() as u32).to_vec();
+        sealed.extend_from_slice(&body);
+        Ok(sealed)
+    }
+    pub(crate) fn open(&self, body: &[u8]) -> Result<Vec<u8>, ConnectionError> {
+        let mut transport = self.transport.lock().unwrap();
+        let mut frame = vec![];
+        let mut payload = [0u8; MAX_NOISE_MESSAGE_SIZE];
+        for message in body.chunks(MAX_NOISE_MESSAGE_SIZE) {
+            let len = transport
+                .read_message(message, &mut payload)
+                .map_err(ConnectionError::EncryptionErr)?;
+            frame.extend_from_slice(&payload[..len]);
+        }
+        Ok(frame)
+    }
+}
+pub(crate) fn max_sealed_size(frame_size: usize) -> usize {
+    frame_size + frame_size.div_ceil(MAX_CHUNK_PAYLOAD_SIZE) * TAG_SIZE
+}
+fn noise_params() -> NoiseParams {
+    NOISE_PATTERN.parse().unwrap()
+}
+pub(crate) async fn client_handshake(
+    stream: &mut TcpStream,
+    pinned_server_key: Option<[u8; 32]>,
+    handshake_timeout: Duration,
+) -> Result<EncryptedSession, ConnectionError> {
+    timeout(handshake_timeout, initiate(stream, pinned_server_key))
+        .await
+        .map_err(|_| ConnectionError::HandshakeTimedOut)?
+}
+pub(crate) async fn server_handshake(
+    stream: &mut TcpStream,
+    keypair: &EncryptionKeypair,
+    handshake_timeout: Duration,
+) -> Result<EncryptedSession, ConnectionError> {
+    timeout(handshake_timeout, respond(stream, keypair))
+        .await
+        .map_err(|_| ConnectionError::HandshakeTimedOut)?
+}
+async fn initiate(
+    stream: &mut TcpStream,
+    pinned_server_key: Option<[u8; 32]>,
+) -> Result<EncryptedSession, ConnectionError> {
+    let builder = Builder::new(noise_params());
+    let keypair = builder
+        .generate_keypair()
+        .map_err(ConnectionError::HandshakeFailed)?;
+    let mut handshake = builder
+        .local_private_key(&keypair.private)
+        .build_initiator()
+        .map_err(ConnectionError::HandshakeFailed)?;
+    write_handshake_message(stream, &mut handshake).await?;
+    read_handshake_message(stream, &mut handshake).await?;
+    if let Some(pinned_server_key) = pinned_server_key {
+        if handshake.get_remote_static() != Some(&pinned_server_key[..]) {
+            return Err(ConnectionError::ServerKeyMismatch);
+        }
+    }
+    write_handshake_message(stream, &mut handshake).await?;
+    into_session(handshake)
+}
+async fn respond(
+    stream: &mut TcpStream,
+    keypair: &EncryptionKeypair,
+) -> Result<EncryptedSession, ConnectionError> {
+    let mut handshake = Builder::new(noise_params())
+        .local_private_key(&keypair.private_key)
+        .build_responder()
+        .map_err(ConnectionError::HandshakeFailed)?;
+    read_handshake_message(stream, &mut handshake).await?;
+    write_handshake_message(stream, &mut handshake).await?;
+    read_handshake_message(stream, &mut handshake).await?;
+    into_session(handshake)
+}
+fn into_session(handshake: HandshakeState) -> Result<EncryptedSession, ConnectionError> {
+    let transport = handshake
+        .into_transport_mode()
+        .map_err(ConnectionError::HandshakeFailed)?;
+    Ok(EncryptedSession {
+        transport: Mutex::new(transport),
+    })
+}
+async fn write_handshake_message(
+    stream: &mut TcpStream,
+    handshake: &mut HandshakeState,
+) -> Result<(), ConnectionError> {
+    let mut message = vec![0u8; MAX_NOISE_MESSAGE_SIZE];
+    let len = handshake
+        .write_message(&[], &mut message)
+        .map_err(ConnectionError::HandshakeFailed)?;
+    stream
+        .write_all(&split_u32_into_u8s(len as u32))
+        .await
+        .map_err(ConnectionError::TcpErr)?;
+    stream
+        .write_all(&message[..len])
+        .await
+        .map_err(ConnectionError::TcpErr)?;
+    Ok(())
+}
+async fn read_handshake_message(
+    stream: &mut TcpStream,
+    handshake: &mut HandshakeState,
+) -> Result<(), ConnectionError> {
+    let mut len = [0u8; 4];
+    stream
+        .read_exact(&mut len)
+        .await
+        .map_err(ConnectionError::TcpErr)?;
+    let len = combine_u8s_into_u32(len) as usize;
+    if len > MAX_NOISE_MESSAGE_SIZE {
+        return Err(ConnectionError::InvalidFrame);
+    }
+    let mut message = vec![0u8; len];
+    stream
+        .read_exact(&mut message)
+        .await
+        .map_err(ConnectionError::TcpErr)?;
+    let mut payload = vec![0u8; MAX_NOISE_MESSAGE_SIZE];
+    handshake
+        .read_message(&message, &mut payload)
+        .map_err(ConnectionError::HandshakeFailed)?;
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use crate::Replicated;
+use bevy::{
+    ecs::{component::ComponentId, world::DeferredWorld},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    cell::RefCell,
+    sync::{Arc, RwLock},
+};
+thread_local! {
+    static ENTITY_MAP: RefCell<Option<NetworkEntityMap>> = const { RefCell::new(None) };
+}
+#[derive(
+    Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+pub struct NetworkEntity(pub u64);
+impl NetworkEntity {
+    pub const PLACEHOLDER: NetworkEntity = NetworkEntity(Entity::PLACEHOLDER.to_bits());
+    pub fn from_server_entity(entity: Entity) -> NetworkEntity {
+        NetworkEntity(entity.to_bits())
+    }
+}
+#[derive(Resource, Clone, Default, Debug)]
+pub struct NetworkEntityMap {
+    maps: Arc<RwLock<EntityMaps>>,
+    is_server: bool,
+}
+#[derive(Default, Debug)]
+struct EntityMaps {
+    to_local: HashMap<NetworkEntity, Entity>,
+    to_network: HashMap<Entity, NetworkEntity>,
+}
+impl NetworkEntityMap {
+    pub(crate) fn server() -> NetworkEntityMap {
+        NetworkEntityMap {
+            is_server: true,
+            ..Default::default()
+        }
+    }
+    // On the server only entities with Replicated are in the map, so whatever else a client
+    // sends doesn't resolve to anything
+    pub fn to_local(&self, network_entity: NetworkEntity) -> Option<Entity> {
+        self.maps
+            .read()
+            .unwrap()
+            .to_local
+            .get(&network_entity)
+            .copied()
+    }
+    pub fn to_network(&self, entity: Entity) -> Option<NetworkEntity> {
+        if self.is_server {
+            return Some(NetworkEntity::from_server_entity(entity));
+        }
+        self.maps.read().unwrap().to_network.get(&entity).copied()
+    }
+    pub fn len(&self) -> usize {
+        self.maps.read().unwrap().to_local.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> bincode::Result<T> {
+        self.scope(|| bincode::deserialize(bytes))
+    }
+    pub(crate) fn insert(&self, network_entity: NetworkEntity, entity: Entity) {
+        let mut maps = self.maps.write().unwrap();
+        maps.to_local.insert(network_entity, entity);
+        maps.to_network.insert(entity, network_entity);
+    }
+    pub(crate) fn remove(&self, network_entity: NetworkEntity) -> Option<Entity> {
+        let mut maps = self.maps.write().unwrap();
+        let entity = maps.to_local.remove(&network_entity)?;
+        maps.to_network.remove(&entity);
+        Some(entity)
+    }
+    pub(crate) fn drain(&self) -> Vec<Entity> {
+        let mut maps = self.maps.write().unwrap();
+        maps.to_network.clear();
+        maps.to_local.drain().map(|(_, entity)| entity).collect()
+    }
+    pub(crate) fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        let previous = ENTITY_MAP.with(|map| map.replace(Some(self.clone())));
+        let result = f();
+        ENTITY_MAP.with(|map| *map.borrow_mut() = previous);
+        result
+    }
+}
+pub(crate) fn track_replicated(world: DeferredWorld, entity: Entity, _: ComponentId) {
+    if let Some(network_entity_map) = world.get_resource::<NetworkEntityMap>() {
+        if network_entity_map.is_server {
+            network_entity_map.insert(NetworkEntity::from_server_entity(entity), entity);
+        }
+    }
+}
+pub(crate) fn untrack_replicated(world: DeferredWorld, entity: Entity, _: ComponentId) {
+    if let Some(network_entity_map) = world.get_resource::<NetworkEntityMap>() {
+        if network_entity_map.is_server {
+            network_entity_map.remove(NetworkEntity::from_server_entity(entity));
+        }
+    }
+}
+pub(crate) fn replicated_entity(world: &World, network_entity: NetworkEntity) -> Option<Entity> {
+    world
+        .resource::<NetworkEntityMap>()
+        .to_local(network_entity)
+        .filter(|entity| world.get::<Replicated>(*entity).is_some())
+}
+pub mod network_entity {
+    use super::{NetworkEntity, ENTITY_MAP};
+    use bevy::prelude::Entity;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    pub fn serialize<S: Serializer>(entity: &Entity, serializer: S) -> Result<S::Ok, S::Error> {
+        let network_entity = ENTITY_MAP.with(|map| match &*map.borrow() {
+            Some(map) => map
+                .to_network(*entity)
+                .unwrap_or(NetworkEntity::PLACEHOLDER),
+            None => NetworkEntity::from_server_entity(*entity),
+        });
+        network_entity.serialize(serializer)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Entity, D::Error> {
+        let network_entity = NetworkEntity::deserialize(deserializer)?;
+        let entity = ENTITY_MAP.with(|map| match &*map.borrow() {
+            Some(map) => map.to_local(network_entity),
+            None => Entity::try_from_bits(network_entity.0).ok(),
+        });
+        Ok(entity.unwrap_or(Entity::PLACEHOLDER))
+    }
+}
+pub mod network_entities {
+    use super::*;
+    pub fn serialize<S: Serializer>(entities: &[Entity], serializer: S) -> Result<S::Ok, S::Error> {
+        let entities: Vec<MappedEntity> = entities.iter().copied().map(MappedEntity).collect();
+        entities.serialize(serializer)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Entity>, D::Error> {
+        let entities = Vec::<MappedEntity>::deserialize(deserializer)?;
+        Ok(entities.into_iter().map(|entity| entity.0).collect())
+    }
+}
+#[derive(Serialize, Deserialize)]
+struct MappedEntity(#[serde(with = "network_entity")] Entity);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[derive(Serialize, Deserialize)]
+    struct Target(#[serde(with = "network_entity")] Entity);
+    fn server_world() -> World {
+        let mut world = World::new();
+        world
+            .register_component_hooks::<Replicated>()
+            .on_add(track_replicated)
+            .on_remove(untrack_replicated);
+        world.insert_resource(NetworkEntityMap::server());
+        world
+    }
+    #[test]
+    fn server_only_resolves_replicated_entities() {
+        let mut world = server_world();
+        let replicated = world.spawn(Replicated).id();
+        let hidden = world.spawn_empty().id();
+        let network_entity_map = world.resource::<NetworkEntityMap>().clone();
+        let replicated_network = NetworkEntity::from_server_entity(replicated);
+        let hidden_network = NetworkEntity::from_server_entity(hidden);
+        assert_eq!(
+            network_entity_map.to_local(replicated_network),
+            Some(replicated)
+        );
+        assert_eq!(network_entity_map.to_local(hidden_network), None);
+        assert_eq!(
+            replicated_entity(&world, replicated_network),
+            Some(replicated)
+        );
+        let bytes = bincode::serialize(&hidden_network).unwrap();
+        let target: Target = network_entity_map.deserialize(&bytes).unwrap();
+        assert_eq!(target.0, Entity::PLACEHOLDER);
+        let bytes = bincode::serialize(&replicated_network).unwrap();
+        let target: Target = network_entity_map.deserialize(&bytes).unwrap();
+        assert_eq!(target.0, replicated);
+        world.entity_mut(replicated).remove::<Replicated>();
+        assert_eq!(network_entity_map.to_local(replicated_network), None);
+        world.entity_mut(replicated).insert(Replicated);
+        world.despawn(replicated);
+        assert_eq!(network_entity_map.to_local(replicated_network), None);
+    }
+    #[test]
+    fn client_maps_entities_both_ways() {
+        let network_entity_map = NetworkEntityMap::default();
+        let local = Entity::from_raw(7);
+        let network_entity = NetworkEntity(42);
+        network_entity_map.insert(network_entity, local);
+        let bytes = network_entity_map
+            .scope(|| bincode::serialize(&Target(local)))
+            .unwrap();
+        assert_eq!(bytes, bincode::serialize(&network_entity).unwrap());
+        let target: Target = network_entity_map.deserialize(&bytes).unwrap();
+        assert_eq!(target.0, local);
+        let unknown = bincode::serialize(&NetworkEntity(43)).unwrap();
+        let target: Target = network_entity_map.deserialize(&unknown).unwrap();
+        assert_eq!(target.0, Entity::PLACEHOLDER);
+        assert_eq!(network_entity_map.remove(network_entity), Some(local));
+        assert_eq!(network_entity_map.to_network(local), None);
+    }
+}
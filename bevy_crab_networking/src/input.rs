@@ -0,0 +1,515 @@
+use crate::{
+    has_client_config, has_server_config, ClientDataUploader, ConnectionChange, Identify,
+    IntergressType, NetworkTick, PlayerIntergressEvent, ServerConnectionChangeEvent,
+    ServerDataReadEvent, NETWORK_INPUT_IDENTIFIER,
+};
+use bevy::{prelude::*, utils::HashMap};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::Debug,
+};
+#[derive(Resource, Clone)]
+pub struct NetworkInputConfig {
+    pub redundancy: usize,
+    pub buffer_size: u32,
+    pub missing: MissingInputPolicy,
+    pub late: LateInputPolicy,
+    pub duplicate: DuplicateInputPolicy,
+}
+impl Default for NetworkInputConfig {
+    fn default() -> Self {
+        NetworkInputConfig {
+            redundancy: 3,
+            buffer_size: 64,
+            missing: MissingInputPolicy::Skip,
+            late: LateInputPolicy::Drop,
+            duplicate: DuplicateInputPolicy::KeepFirst,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissingInputPolicy {
+    Skip,
+    RepeatLast,
+    Default,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LateInputPolicy {
+    Drop,
+    Apply,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicateInputPolicy {
+    KeepFirst,
+    KeepLast,
+}
+pub trait NetworkInputData:
+    Serialize + DeserializeOwned + Clone + Default + Debug + Send + Sync + 'static
+{
+}
+impl<T: Serialize + DeserializeOwned + Clone + Default + Debug + Send + Sync + 'static>
+    NetworkInputData for T
+{
+}
+#[derive(Resource, Default)]
+pub struct NetworkInput<T>(pub T);
+#[derive(Clone, Debug)]
+pub struct TickInput<T> {
+    pub tick: u32,
+    pub input: T,
+    pub missing: bool,
+}
+#[derive(Resource)]
+pub struct NetworkInputs<T> {
+    index: u16,
+    clients: HashMap<u32, ClientInputs<T>>,
+}
+impl<T> NetworkInputs<T> {
+    pub fn current(&self, id: u32) -> Option<&TickInput<T>> {
+        self.clients
+            .get(&id)
+            .and_then(|client| client.current.as_ref())
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &TickInput<T>)> {
+        self.clients
+            .iter()
+            .filter_map(|(id, client)| client.current.as_ref().map(|current| (*id, current)))
+    }
+    pub fn get(&self, id: u32, tick: u32) -> Option<&T> {
+        self.clients
+            .get(&id)
+            .and_then(|client| client.received.get(&tick))
+    }
+    pub fn latest_tick(&self, id: u32) -> Option<u32> {
+        self.clients
+            .get(&id)
+            .and_then(|client| newest_tick(client.received.keys().copied()))
+    }
+    pub fn clients(&self) -> impl Iterator<Item = u32> + '_ {
+        self.clients.keys().copied()
+    }
+}
+pub trait NetworkInputApp {
+    fn add_network_input<T: NetworkInputData>(&mut self) -> &mut Self;
+}
+impl NetworkInputApp for App {
+    fn add_network_input<T: NetworkInputData>(&mut self) -> &mut Self {
+        if self.world().contains_resource::<NetworkInputs<T>>() {
+            return self;
+        }
+        self.init_resource::<InputRegistry>();
+        let mut registry = self.world_mut().resource_mut::<InputRegistry>();
+        let index = registry.inputs;
+        registry.inputs += 1;
+        self.init_resource::<NetworkInput<T>>()
+            .insert_resource(SentInputs::<T> {
+                index,
+                history: VecDeque::new(),
+            })
+            .insert_resource(NetworkInputs::<T> {
+                index,
+                clients: HashMap::new(),
+            })
+            .add_systems(FixedPreUpdate, send_input::<T>.run_if(has_client_config))
+            .add_systems(PreUpdate, reset_sent_inputs::<T>.run_if(has_client_config))
+            .add_systems(PreUpdate, receive_inputs::<T>.run_if(has_server_config))
+            .add_systems(
+                FixedPreUpdate,
+                advance_inputs::<T>.run_if(has_server_config),
+            )
+    }
+}
+#[derive(Resource, Default)]
+struct InputRegistry {
+    inputs: u16,
+}
+#[derive(Serialize, Deserialize, Debug)]
+struct InputPacket<T> {
+    index: u16,
+    tick: u32,
+    inputs: Vec<T>,
+}
+impl<T> Identify for InputPacket<T> {
+    fn get_identifier(&self) -> u32 {
+        NETWORK_INPUT_IDENTIFIER
+    }
+}
+#[derive(Resource)]
+pub(crate) struct SentInputs<T> {
+    index: u16,
+    history: VecDeque<(u32, T)>,
+}
+impl<T> SentInputs<T> {
+    pub(crate) fn latest(&self) -> Option<(u32, &T)> {
+        self.history.front().map(|(tick, input)| (*tick, input))
+    }
+}
+struct ClientInputs<T> {
+    received: BTreeMap<u32, T>,
+    skipped: BTreeSet<u32>,
+    late: VecDeque<(u32, T)>,
+    next_tick: Option<u32>,
+    last: Option<T>,
+    current: Option<TickInput<T>>,
+}
+impl<T> Default for ClientInputs<T> {
+    fn default() -> Self {
+        ClientInputs {
+            received: BTreeMap::new(),
+            skipped: BTreeSet::new(),
+            late: VecDeque::new(),
+            next_tick: None,
+            last: None,
+            current: None,
+        }
+    }
+}
+impl<T: NetworkInputData> ClientInputs<T> {
+    fn receive(&mut self, tick: u32, input: T, config: &NetworkInputConfig) {
+        if self
+            .next_tick
+            .is_some_and(|next_tick| tick_before(tick, next_tick))
+        {
+            if self.received.contains_key(&tick) {
+                if config.duplicate == DuplicateInputPolicy::KeepLast {
+                    self.received.insert(tick, input);
+                }
+            } else if self.skipped.remove(&tick) && config.late == LateInputPolicy::Apply {
+                self.received.insert(tick, input.clone());
+                self.late.push_back((tick, input));
+            }
+            return;
+        }
+        match self.received.get_mut(&tick) {
+            Some(received) => {
+                if config.duplicate == DuplicateInputPolicy::KeepLast {
+                    *received = input;
+                }
+            }
+            None => {
+                self.received.insert(tick, input);
+            }
+        }
+        let Some(newest) = newest_tick(self.received.keys().copied()) else {
+            return;
+        };
+        let oldest = newest.wrapping_sub(config.buffer_size);
+        self.received.retain(|tick, _| !tick_before(*tick, oldest));
+        self.skipped.retain(|tick| !tick_before(*tick, oldest));
+        if self
+            .next_tick
+            .is_some_and(|next_tick| tick_before(next_tick, oldest))
+        {
+            self.next_tick = oldest_tick(self.received.keys().copied());
+        }
+    }
+    fn advance(&mut self, config: &NetworkInputConfig) {
+        self.current = None;
+        if let Some((tick, input)) = self.late.pop_front() {
+            self.current = Some(TickInput {
+                tick,
+                input,
+                missing: false,
+            });
+            return;
+        }
+        let Some(tick) = self
+            .next_tick
+            .or_else(|| oldest_tick(self.received.keys().copied()))
+        else {
+            return;
+        };
+        if let Some(input) = self.received.get(&tick) {
+            self.last = Some(input.clone());
+            self.current = Some(TickInput {
+                tick,
+                input: input.clone(),
+                missing: false,
+            });
+            self.next_tick = Some(tick.wrapping_add(1));
+            return;
+        }
+        if self
+            .received
+            .keys()
+            .any(|received| !tick_before(*received, tick))
+        {
+            self.skipped.insert(tick);
+            self.next_tick = Some(tick.wrapping_add(1));
+        } else {
+            self.next_tick = Some(tick);
+        }
+        let input = match config.missing {
+            MissingInputPolicy::Skip => None,
+            MissingInputPolicy::RepeatLast => self.last.clone(),
+            MissingInputPolicy::Default => Some(T::default()),
+        };
+        self.current = input.map(|input| TickInput {
+            tick,
+            input,
+            missing: true,
+        });
+    }
+}
+fn tick_before(tick: u32, other: u32) -> bool {
+    (tick.wrapping_sub(other) as i32) < 0
+}
+fn newest_tick(ticks: impl Iterator<Item = u32>) -> Option<u32> {
+    ticks.reduce(|newest, tick| match tick_before(newest, tick) {
+        true => tick,
+        false => newest,
+    })
+}
+fn oldest_tick(ticks: impl Iterator<Item = u32>) -> Option<u32> {
+    ticks.reduce(|oldest, tick| match tick_before(tick, oldest) {
+        true => tick,
+        false => oldest,
+    })
+}
+pub(crate) struct InputPlugin;
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkInputConfig>();
+    }
+}
+fn send_input<T: NetworkInputData>(
+    mut sent_inputs: ResMut<SentInputs<T>>,
+    client_data_uploader: Option<ResMut<ClientDataUploader>>,
+    network_input: Res<NetworkInput<T>>,
+    network_input_config: Res<NetworkInputConfig>,
+    network_tick: Res<NetworkTick>,
+) {
+    let Some(mut client_data_uploader) = client_data_uploader else {
+        return;
+    };
+    if !client_data_uploader.is_connected() {
+        return;
+    }
+    let tick = network_tick.get();
+    sent_inputs
+        .history
+        .push_front((tick, network_input.0.clone()));
+    sent_inputs
+        .history
+        .truncate(network_input_config.redundancy + 1);
+    let _ = client_data_uploader.upload(InputPacket {
+        index: sent_inputs.index,
+        tick,
+        inputs: sent_inputs
+            .history
+            .iter()
+            .zip(0..)
+            .take_while(|((sent_tick, _), age)| *sent_tick == tick.wrapping_sub(*age))
+            .map(|((_, input), _)| input.clone())
+            .collect(),
+    });
+}
+fn reset_sent_inputs<T: NetworkInputData>(
+    mut server_connection_change_reader: EventReader<ServerConnectionChangeEvent>,
+    mut sent_inputs: ResMut<SentInputs<T>>,
+) {
+    for event in server_connection_change_reader.read() {
+        if let ConnectionChange::Disconnected = event.connection_change {
+            sent_inputs.history.clear();
+        }
+    }
+}
+fn receive_inputs<T: NetworkInputData>(
+    mut server_data_reader: EventReader<ServerDataReadEvent>,
+    mut player_intergress_reader: EventReader<PlayerIntergressEvent>,
+    mut network_inputs: ResMut<NetworkInputs<T>>,
+    network_input_config: Res<NetworkInputConfig>,
+    network_tick: Res<NetworkTick>,
+) {
+    for event in player_intergress_reader.read() {
+        if let IntergressType::Left = event.intergress_type {
+            network_inputs.clients.remove(&event.id);
+        }
+    }
+    for event in server_data_reader.read() {
+        if event.data_packet.identifier != NETWORK_INPUT_IDENTIFIER
+            || bincode::deserialize::<u16>(&event.data_packet.bytes).ok()
+                != Some(network_inputs.index)
+        {
+            continue;
+        }
+        let packet: InputPacket<T> = match bincode::deserialize(&event.data_packet.bytes) {
+            Ok(packet) => packet,
+            Err(err) => {
+                println!(
+                    "Failed to deserialize an input from id {}: {err:#?}",
+                    event.id
+                );
+                continue;
+            }
+        };
+        let client = network_inputs.clients.entry(event.id).or_default();
+        for (age, input) in packet.inputs.into_iter().enumerate().rev() {
+            let tick = packet.tick.wrapping_sub(age as u32);
+            // Anything further than the buffer from our own tick would only push out the
+            // inputs we still need
+            let distance = (tick.wrapping_sub(network_tick.get()) as i32).unsigned_abs();
+            if distance <= network_input_config.buffer_size {
+                client.receive(tick, input, &network_input_config);
+            }
+        }
+    }
+}
+fn advance_inputs<T: NetworkInputData>(
+    mut network_inputs: ResMut<NetworkInputs<T>>,
+    network_input_config: Res<NetworkInputConfig>,
+) {
+    for client in network_inputs.clients.values_mut() {
+        client.advance(&network_input_config);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn advanced(client: &mut ClientInputs<u32>, config: &NetworkInputConfig) -> Option<(u32, u32)> {
+        client.advance(config);
+        client
+            .current
+            .as_ref()
+            .map(|current| (current.tick, current.input))
+    }
+    #[test]
+    fn advances_across_tick_wrap() {
+        let config = NetworkInputConfig::default();
+        let mut client = ClientInputs::default();
+        for tick in [u32::MAX - 1, u32::MAX, 0, 1] {
+            client.receive(tick, tick, &config);
+        }
+        for tick in [u32::MAX - 1, u32::MAX, 0, 1] {
+            assert_eq!(advanced(&mut client, &config), Some((tick, tick)));
+        }
+        assert_eq!(client.next_tick, Some(2));
+    }
+    #[test]
+    fn ticks_before_a_wrap_are_late() {
+        let config = NetworkInputConfig::default();
+        let mut client = ClientInputs::default();
+        client.receive(0, 0, &config);
+        assert_eq!(advanced(&mut client, &config), Some((0, 0)));
+        client.receive(u32::MAX, u32::MAX, &config);
+        assert!(!client.received.contains_key(&u32::MAX));
+        assert_eq!(advanced(&mut client, &config), None);
+        assert_eq!(client.next_tick, Some(1));
+    }
+    #[test]
+    fn prunes_across_tick_wrap() {
+        let config = NetworkInputConfig {
+            buffer_size: 4,
+            ..Default::default()
+        };
+        let mut client = ClientInputs::default();
+        client.receive(u32::MAX - 10, 0, &config);
+        client.receive(u32::MAX - 1, 1, &config);
+        client.receive(2, 2, &config);
+        assert_eq!(
+            client.received.keys().copied().collect::<Vec<_>>(),
+            vec![2, u32::MAX - 1]
+        );
+        assert_eq!(advanced(&mut client, &config), Some((u32::MAX - 1, 1)));
+    }
+    fn current(client: &ClientInputs<u32>) -> Option<(u32, u32, bool)> {
+        client
+            .current
+            .as_ref()
+            .map(|current| (current.tick, current.input, current.missing))
+    }
+    fn with_gap(config: &NetworkInputConfig) -> ClientInputs<u32> {
+        let mut client = ClientInputs::default();
+        client.receive(0, 10, config);
+        client.receive(2, 12, config);
+        assert_eq!(advanced(&mut client, config), Some((0, 10)));
+        client
+    }
+    #[test]
+    fn fills_missing_inputs_by_policy() {
+        let cases = [
+            (MissingInputPolicy::Skip, None),
+            (MissingInputPolicy::RepeatLast, Some((1, 10, true))),
+            (MissingInputPolicy::Default, Some((1, 0, true))),
+        ];
+        for (missing, expected) in cases {
+            let config = NetworkInputConfig {
+                missing,
+                ..Default::default()
+            };
+            let mut client = with_gap(&config);
+            client.advance(&config);
+            assert_eq!(current(&client), expected);
+            client.advance(&config);
+            assert_eq!(current(&client), Some((2, 12, false)));
+        }
+    }
+    #[test]
+    fn waits_when_nothing_newer_arrived() {
+        let config = NetworkInputConfig {
+            missing: MissingInputPolicy::RepeatLast,
+            ..Default::default()
+        };
+        let mut client = ClientInputs::default();
+        client.receive(0, 10, &config);
+        client.advance(&config);
+        client.advance(&config);
+        assert_eq!(current(&client), Some((1, 10, true)));
+        client.receive(1, 11, &config);
+        client.advance(&config);
+        assert_eq!(current(&client), Some((1, 11, false)));
+    }
+    #[test]
+    fn handles_late_inputs_by_policy() {
+        let config = NetworkInputConfig::default();
+        let mut client = with_gap(&config);
+        client.advance(&config);
+        client.receive(1, 11, &config);
+        assert_eq!(advanced(&mut client, &config), Some((2, 12)));
+        let config = NetworkInputConfig {
+            late: LateInputPolicy::Apply,
+            ..Default::default()
+        };
+        let mut client = with_gap(&config);
+        client.advance(&config);
+        client.receive(1, 11, &config);
+        client.receive(1, 21, &config);
+        assert_eq!(advanced(&mut client, &config), Some((1, 11)));
+        assert_eq!(advanced(&mut client, &config), Some((2, 12)));
+    }
+    #[test]
+    fn handles_duplicate_inputs_by_policy() {
+        for (duplicate, expected) in [
+            (DuplicateInputPolicy::KeepFirst, 1),
+            (DuplicateInputPolicy::KeepLast, 2),
+        ] {
+            let config = NetworkInputConfig {
+                duplicate,
+                ..Default::default()
+            };
+            let mut client = ClientInputs::default();
+            client.receive(0, 1, &config);
+            client.receive(0, 2, &config);
+            assert_eq!(advanced(&mut client, &config), Some((0, expected)));
+        }
+    }
+    #[test]
+    fn keeps_buffer_size_ticks() {
+        let config = NetworkInputConfig {
+            buffer_size: 4,
+            ..Default::default()
+        };
+        let mut client = ClientInputs::default();
+        for tick in 0..=10 {
+            client.receive(tick, tick, &config);
+        }
+        assert_eq!(
+            client.received.keys().copied().collect::<Vec<_>>(),
+            vec![6, 7, 8, 9, 10]
+        );
+        assert_eq!(advanced(&mut client, &config), Some((6, 6)));
+        client.receive(100, 100, &config);
+        assert_eq!(advanced(&mut client, &config), Some((100, 100)));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{has_client_config, snapshot::SnapshotTime};
+use bevy::prelude::*;
+use std::{collections::VecDeque, time::Duration};
+const CLOCK_SMOOTHING: f64 = 0.1;
+#[derive(Resource)]
+pub struct InterpolationConfig {
+    pub delay: Duration,
+}
+impl Default for InterpolationConfig {
+    fn default() -> Self {
+        InterpolationConfig {
+            delay: Duration::from_millis(100),
+        }
+    }
+}
+#[derive(Component)]
+pub struct InterpolationBuffer<C> {
+    samples: VecDeque<(f64, C)>,
+}
+impl<C> InterpolationBuffer<C> {
+    pub fn latest(&self) -> Option<&C> {
+        self.samples.back().map(|(_, component)| component)
+    }
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+pub trait InterpolationApp {
+    fn interpolate<C: Component + Clone>(&mut self, interpolate: fn(&C, &C, f32) -> C)
+        -> &mut Self;
+}
+impl InterpolationApp for App {
+    fn interpolate<C: Component + Clone>(
+        &mut self,
+        interpolate: fn(&C, &C, f32) -> C,
+    ) -> &mut Self {
+        self.insert_resource(Interpolation::<C> {
+            interpolate,
+            receive: receive_interpolated::<C>,
+        })
+        .add_systems(
+            PreUpdate,
+            interpolate_component::<C>
+                .in_set(InterpolationSet)
+                .run_if(has_client_config),
+        )
+    }
+}
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct InterpolationSet;
+#[derive(Resource)]
+pub(crate) struct Interpolation<C> {
+    interpolate: fn(&C, &C, f32) -> C,
+    pub(crate) receive: fn(&mut EntityWorldMut, C, SnapshotTime),
+}
+#[derive(Resource, Default)]
+pub(crate) struct ServerClock {
+    offset: Option<f64>,
+}
+impl ServerClock {
+    pub(crate) fn observe(&mut self, server_time: f64, local_time: f64) {
+        let sample = server_time - local_time;
+        self.offset = Some(match self.offset {
+            Some(offset) => offset + (sample - offset) * CLOCK_SMOOTHING,
+            None => sample,
+        });
+    }
+    pub(crate) fn reset(&mut self) {
+        self.offset = None;
+    }
+    fn server_time(&self, local_time: f64) -> Option<f64> {
+        self.offset.map(|offset| local_time + offset)
+    }
+}
+pub(crate) struct InterpolationPlugin;
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InterpolationConfig>()
+            .init_resource::<ServerClock>();
+    }
+}
+pub fn interpolate_transform(from: &Transform, to: &Transform, t: f32) -> Transform {
+    Transform {
+        translation: from.translation.lerp(to.translation, t),
+        rotation: from.rotation.slerp(to.rotation, t),
+        scale: from.scale.lerp(to.scale, t),
+    }
+}
+fn receive_interpolated<C: Component + Clone>(
+    entity: &mut EntityWorldMut,
+    component: C,
+    time: SnapshotTime,
+) {
+    match entity.get_mut::<InterpolationBuffer<C>>() {
+        Some(mut buffer) => {
+            let held = buffer
+                .samples
+                .back()
+                .filter(|(sample_time, _)| *sample_time < time.previous_time)
+                .map(|(_, component)| component.clone());
+            if let Some(held) = held {
+                buffer.samples.push_back((time.previous_time, held));
+            }
+            buffer.samples.push_back((time.time, component));
+        }
+        None => {
+            entity.insert((
+                InterpolationBuffer {
+                    samples: VecDeque::from([(time.time, component.clone())]),
+                },
+                component,
+            ));
+        }
+    }
+}
+fn interpolate_component<C: Component + Clone>(
+    mut interpolated: Query<(&mut InterpolationBuffer<C>, &mut C)>,
+    interpolation: Res<Interpolation<C>>,
+    interpolation_config: Res<InterpolationConfig>,
+    server_clock: Res<ServerClock>,
+    time: Res<Time<Real>>,
+) {
+    let Some(server_time) = server_clock.server_time(time.elapsed_seconds_f64()) else {
+        return;
+    };
+    let render_time = server_time - interpolation_config.delay.as_secs_f64();
+    for (mut buffer, mut component) in interpolated.iter_mut() {
+        while buffer.samples.len() > 1 && buffer.samples[1].0 <= render_time {
+            buffer.samples.pop_front();
+        }
+        let value = match (buffer.samples.front(), buffer.samples.get(1)) {
+            (Some((from_time, from)), Some((to_time, to))) if *from_time < render_time => {
+                let t = (render_time - from_time) / (to_time - from_time);
+                (interpolation.interpolate)(from, to, t.clamp(0., 1.) as f32)
+            }
+            (Some((_, from)), _) => from.clone(),
+            (None, _) => continue,
+        };
+        *component = value;
+    }
+}
@@ -1,18 +1,100 @@
-use bevy::{ecs::system::RunSystemOnce, prelude::*, tasks::IoTaskPool, utils::HashMap};
+use bevy::{ecs::system::RunSystemOnce, prelude::*, utils::HashMap};
 use bevy_crossbeam_event::{CrossbeamEventApp, CrossbeamEventSender};
 use bincode::ErrorKind;
+use bytes::BytesMut;
 use serde::{Deserialize, Serialize};
 use std::{
     fmt::Debug,
-    io::Write,
-    net::{SocketAddr, TcpListener, TcpStream},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
     },
+    time::Duration,
 };
-use tokio::{io::AsyncReadExt, runtime::Builder};
-const CHUNK_SIZE: usize = 128;
+use tick::TickSet;
+use tokio::io::AsyncReadExt;
+mod authority;
+mod compression;
+mod encryption;
+mod entity_map;
+mod input;
+mod interpolation;
+#[cfg(feature = "lobby")]
+mod lobby;
+mod lockstep;
+mod network_event;
+mod prediction;
+mod priority;
+mod relevance;
+mod replication;
+mod resource;
+#[cfg(feature = "rollback")]
+mod rollback;
+mod room;
+mod rpc;
+mod runtime;
+mod snapshot;
+mod socket;
+mod stats;
+mod tick;
+pub use authority::{
+    Authority, AuthorityApp, AuthorityRequestEvent, LocalClientId, RequestAuthority,
+};
+pub use bytes::Bytes;
+pub use compression::Compression;
+pub use encryption::{ClientEncryption, EncryptedSession, EncryptionKeypair, ServerEncryption};
+pub use entity_map::{network_entities, network_entity, NetworkEntity, NetworkEntityMap};
+pub use input::{
+    DuplicateInputPolicy, LateInputPolicy, MissingInputPolicy, NetworkInput, NetworkInputApp,
+    NetworkInputConfig, NetworkInputData, NetworkInputs, TickInput,
+};
+pub use interpolation::{
+    interpolate_transform, InterpolationApp, InterpolationBuffer, InterpolationConfig,
+};
+#[cfg(feature = "lobby")]
+pub use lobby::{
+    Lobbies, LobbyConfig, LobbyError, LobbyEvent, LobbyId, LobbyInfo, LobbyPlayer, LobbyPlugin,
+    LobbyRequest, LobbyResponse, LobbyStarted, LobbyTicket,
+};
+pub use lockstep::{LockstepApp, LockstepConfig, LockstepDesync, LockstepInputs};
+pub use network_event::{FromClient, NetworkDirection, NetworkEventApp, ToClients, ToServer};
+pub use prediction::{InputOwner, Predicted, PredictedInput, PredictionApp, ProcessedInputTick};
+pub use priority::{NetworkPriority, PriorityContext};
+pub use relevance::{
+    NetworkRelevance, NetworkTeams, NetworkViewer, RelevanceConfig, RelevantEntities,
+};
+pub use replication::{Replicated, ReplicationApp, ReplicationConfig};
+#[cfg(feature = "rollback")]
+pub use rollback::{Rollback, RollbackApp, RollbackConfig, RollbackHarness, RollbackInputs};
+pub use room::{ClientMetadata, CurrentRoom, InRoom, NetworkRooms, RoomApp};
+pub use rpc::{
+    RequestId, Rpc, RpcApp, RpcClient, RpcConfig, RpcError, RpcRepliesEvent, RpcResponseEvent,
+    RpcServer,
+};
+pub use runtime::{NetworkRuntime, NetworkRuntimeConfig};
+pub use socket::SocketOptions;
+pub use stats::NetworkStats;
+pub use tick::{NetworkTick, NetworkTickConfig};
+const HEADER_SIZE: usize = 13;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+pub type RecipientFilter = Box<dyn Fn(u32, &ClientMetadata) -> bool + Send + Sync>;
+pub(crate) const REPLICATION_IDENTIFIER: u32 = u32::MAX;
+pub(crate) const REPLICATION_ACK_IDENTIFIER: u32 = u32::MAX - 1;
+pub(crate) const NETWORK_INPUT_IDENTIFIER: u32 = u32::MAX - 2;
+pub(crate) const TICK_SYNC_IDENTIFIER: u32 = u32::MAX - 3;
+pub(crate) const LOCKSTEP_IDENTIFIER: u32 = u32::MAX - 4;
+#[cfg(feature = "rollback")]
+pub(crate) const ROLLBACK_IDENTIFIER: u32 = u32::MAX - 5;
+pub(crate) const RESOURCE_IDENTIFIER: u32 = u32::MAX - 6;
+pub(crate) const AUTHORITY_IDENTIFIER: u32 = u32::MAX - 7;
+pub(crate) const RPC_IDENTIFIER: u32 = u32::MAX - 8;
+pub(crate) const NETWORK_EVENT_IDENTIFIER: u32 = u32::MAX - 9;
+// Everything from the lowest identifier above up is used by the crate's own packets
+pub(crate) fn is_internal_identifier(identifier: u32) -> bool {
+    identifier >= NETWORK_EVENT_IDENTIFIER
+}
 #[derive(Debug)]
 pub enum ConnectionError {
     MissingClientConfig,
@@ -26,6 +108,13 @@ pub enum ConnectionError {
     FailedToSendData(std::io::Error),
     TcpErr(std::io::Error),
     BincodeErr(bincode::Error),
+    HandshakeFailed(snow::Error),
+    HandshakeTimedOut,
+    EncryptionErr(snow::Error),
+    ServerKeyMismatch,
+    InvalidFrame,
+    FrameTooLarge(usize),
+    DecompressionFailed(std::io::Error),
 }
 #[derive(Event, Clone)]
 pub struct ServerConnectionChangeEvent {
@@ -39,11 +128,13 @@ pub enum ConnectionChange {
 #[derive(Event, Clone, Debug)]
 pub struct ClientDataReadEvent {
     pub data_packet: DataPacket,
+    pub tick: u32,
 }
 #[derive(Event, Clone, Debug)]
 pub struct ServerDataReadEvent {
     pub data_packet: DataPacket,
     pub id: u32,
+    pub tick: u32,
 }
 #[derive(Event, Clone)]
 pub struct PlayerIntergressEvent {
@@ -53,16 +144,36 @@ pub struct PlayerIntergressEvent {
 #[derive(Resource)]
 pub struct ServerConfig {
     pub host_port: u16,
+    pub encryption: ServerEncryption,
+    pub compression: Compression,
+    pub socket_options: SocketOptions,
+    pub batching: Batching,
+    pub max_frame_size: usize,
+    pub handshake_timeout: Duration,
 }
 impl Default for ServerConfig {
     fn default() -> Self {
-        ServerConfig { host_port: 46393 }
+        ServerConfig {
+            host_port: 46393,
+            encryption: ServerEncryption::None,
+            compression: Compression::None,
+            socket_options: SocketOptions::default(),
+            batching: Batching::PerTick,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+        }
     }
 }
 #[derive(Resource)]
 pub struct ClientConfig {
     pub server_address: SocketAddr,
     pub auto_reconnect: AutoReconnect,
+    pub encryption: ClientEncryption,
+    pub compression: Compression,
+    pub socket_options: SocketOptions,
+    pub batching: Batching,
+    pub max_frame_size: usize,
+    pub handshake_timeout: Duration,
 }
 impl Default for ClientConfig {
     fn default() -> Self {
@@ -71,6 +182,12 @@ impl Default for ClientConfig {
             auto_reconnect: AutoReconnect::Auto {
                 reconnection_time: 5.,
             },
+            encryption: ClientEncryption::None,
+            compression: Compression::None,
+            socket_options: SocketOptions::default(),
+            batching: Batching::PerTick,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
         }
     }
 }
@@ -78,22 +195,34 @@ pub enum AutoReconnect {
     Auto { reconnection_time: f32 },
     None,
 }
+pub enum Batching {
+    PerTick,
+    MaxSize { bytes: usize },
+}
 #[derive(Clone, Debug)]
 pub struct DataPacket {
     pub identifier: u32,
-    pub bytes: Vec<u8>,
+    pub bytes: Bytes,
+}
+pub struct NetworkStream {
+    pub tcp_stream: TcpStream,
+    pub session: Option<Arc<EncryptedSession>>,
+    pub compression: Compression,
+    pub stats: NetworkStats,
+    pending_writes: Mutex<socket::PendingWrites>,
 }
 #[derive(Resource)]
 pub struct ServerStreams {
-    pub streams: Arc<Mutex<HashMap<u32, TcpStream>>>,
+    pub streams: Arc<Mutex<HashMap<u32, NetworkStream>>>,
 }
 #[derive(Resource)]
 pub struct ClientStream {
-    pub stream: Arc<Mutex<Option<TcpStream>>>,
+    pub stream: Arc<Mutex<Option<NetworkStream>>>,
 }
 #[derive(Resource, Debug)]
 pub struct ClientDataUploader {
     pub list: Option<Vec<DataPacket>>,
+    network_entity_map: NetworkEntityMap,
 }
 #[derive(Resource)]
 pub struct ServerDataUploader {
@@ -113,17 +242,50 @@ pub enum Recipient {
     All,
     AllExcept { id: u32 },
     Single { id: u32 },
+    Relevant { entity: Entity },
+    Room(String),
+    Many(Vec<u32>),
+    Filter(RecipientFilter),
+}
+impl Recipient {
+    pub fn room(room: impl Into<String>) -> Recipient {
+        Recipient::Room(room.into())
+    }
+    pub fn filter(
+        filter: impl Fn(u32, &ClientMetadata) -> bool + Send + Sync + 'static,
+    ) -> Recipient {
+        Recipient::Filter(Box::new(filter))
+    }
+    pub(crate) fn includes(
+        &self,
+        id: u32,
+        relevant_entities: &RelevantEntities,
+        network_rooms: &NetworkRooms,
+    ) -> bool {
+        match self {
+            Recipient::All => true,
+            Recipient::AllExcept { id: except } => id != *except,
+            Recipient::Single { id: single } => id == *single,
+            Recipient::Relevant { entity } => relevant_entities.is_relevant(id, *entity),
+            Recipient::Room(room) => network_rooms.contains(room, id),
+            Recipient::Many(ids) => ids.contains(&id),
+            Recipient::Filter(filter) => match network_rooms.metadata(id) {
+                Some(client_metadata) => filter(id, client_metadata),
+                None => filter(id, &ClientMetadata::default()),
+            },
+        }
+    }
 }
 enum StreamEndpoint {
     Client {
         client_data_read_sender: CrossbeamEventSender<ClientDataReadEvent>,
         server_connection_change_sender: CrossbeamEventSender<ServerConnectionChangeEvent>,
-        client_stream: Arc<Mutex<Option<TcpStream>>>,
+        client_stream: Arc<Mutex<Option<NetworkStream>>>,
         stop_flag: Arc<AtomicBool>,
     },
     Server {
         id: u32,
-        server_streams: Arc<Mutex<HashMap<u32, TcpStream>>>,
+        server_streams: Arc<Mutex<HashMap<u32, NetworkStream>>>,
         server_data_read_sender: CrossbeamEventSender<ServerDataReadEvent>,
         player_intergress_sender: CrossbeamEventSender<PlayerIntergressEvent>,
     },
@@ -136,13 +298,13 @@ impl ClientDataUploader {
         &mut self,
         data: T,
     ) -> Result<(), ConnectionError> {
-        let bytes = bincode::serialize(&data);
+        let bytes = self.network_entity_map.scope(|| bincode::serialize(&data));
         match bytes {
             Ok(bytes) => match &mut self.list {
                 Some(list) => {
                     list.push(DataPacket {
                         identifier: data.get_identifier(),
-                        bytes,
+                        bytes: bytes.into(),
                     });
                     return Ok(());
                 }
@@ -170,7 +332,7 @@ impl ServerDataUploader {
                     recipient,
                     DataPacket {
                         identifier: data.get_identifier(),
-                        bytes,
+                        bytes: bytes.into(),
                     },
                 ));
             }
@@ -183,34 +345,67 @@ impl ServerDataUploader {
 pub struct BevyCrabNetworkingPlugin;
 impl Plugin for BevyCrabNetworkingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_crossbeam_event::<ClientDataReadEvent>()
-            .add_crossbeam_event::<ServerDataReadEvent>()
-            .add_crossbeam_event::<PlayerIntergressEvent>()
-            .add_crossbeam_event::<ServerConnectionChangeEvent>()
-            .add_systems(PreStartup, client_specific_setup.run_if(has_client_config))
-            .add_systems(PreStartup, server_specific_setup.run_if(has_server_config))
-            .add_systems(Startup, setup)
-            .add_systems(Update, reconnect.run_if(has_client_config))
-            .add_systems(Update, send_data_to_server.run_if(is_connected_to_server))
-            .add_systems(Update, send_data_to_clients.run_if(has_server_config));
+        app.add_plugins((
+            replication::ReplicationPlugin,
+            relevance::RelevancePlugin,
+            priority::PriorityPlugin,
+            room::RoomPlugin,
+            authority::AuthorityPlugin,
+            interpolation::InterpolationPlugin,
+            input::InputPlugin,
+            tick::TickPlugin,
+        ))
+        .add_crossbeam_event::<ClientDataReadEvent>()
+        .add_crossbeam_event::<ServerDataReadEvent>()
+        .add_crossbeam_event::<PlayerIntergressEvent>()
+        .add_crossbeam_event::<ServerConnectionChangeEvent>()
+        .init_resource::<NetworkStats>()
+        .add_systems(PreStartup, runtime::runtime_setup)
+        .add_systems(PreStartup, client_specific_setup.run_if(has_client_config))
+        .add_systems(PreStartup, server_specific_setup.run_if(has_server_config))
+        .add_systems(Startup, setup)
+        .add_systems(Update, reconnect.run_if(has_client_config))
+        .add_systems(
+            FixedPostUpdate,
+            send_data_to_server
+                .in_set(TickSet::Flush)
+                .run_if(is_connected_to_server),
+        )
+        .add_systems(
+            FixedPostUpdate,
+            send_data_to_clients
+                .in_set(TickSet::Flush)
+                .run_if(has_server_config),
+        )
+        .add_systems(Last, runtime::shutdown_runtime);
     }
 }
 fn client_specific_setup(mut commands: Commands) {
-    commands.insert_resource(ClientDataUploader { list: None });
+    let network_entity_map = NetworkEntityMap::default();
+    commands.insert_resource(ClientDataUploader {
+        list: None,
+        network_entity_map: network_entity_map.clone(),
+    });
+    commands.insert_resource(network_entity_map);
     commands.insert_resource(ClientStream {
         stream: Arc::new(Mutex::new(None)),
     });
 }
-fn server_specific_setup(mut commands: Commands) {
+fn server_specific_setup(mut commands: Commands, replicated: Query<Entity, With<Replicated>>) {
+    let network_entity_map = NetworkEntityMap::server();
+    for entity in replicated.iter() {
+        network_entity_map.insert(NetworkEntity::from_server_entity(entity), entity);
+    }
     commands.insert_resource(ServerDataUploader { list: vec![] });
+    commands.insert_resource(network_entity_map);
     commands.insert_resource(ServerStreams {
         streams: Arc::new(Mutex::new(HashMap::new())),
     });
 }
-fn has_client_config(resource: Option<Res<ClientConfig>>) -> bool {
+pub(crate) fn has_client_config(resource: Option<Res<ClientConfig>>) -> bool {
     resource.is_some()
 }
-fn has_server_config(resource: Option<Res<ServerConfig>>) -> bool {
+pub(crate) fn has_server_config(resource: Option<Res<ServerConfig>>) -> bool {
     resource.is_some()
 }
 pub fn is_connected_to_server(data_uploader: Option<Res<ClientDataUploader>>) -> bool {
@@ -224,6 +419,7 @@ pub fn is_connected_to_server(data_uploader: Option<Res<ClientDataUploader>>) ->
 pub fn disconnect_from_server(
     client_config: Option<Res<ClientConfig>>,
     client_read_stop_flag: Option<ResMut<ClientReadStopFlag>>,
+    client_stream: Option<Res<ClientStream>>,
 ) -> Result<(), ConnectionError> {
     if let None = client_config {
         return Err(ConnectionError::MissingClientConfig);
@@ -231,6 +427,12 @@ pub fn disconnect_from_server(
     if let Some(client_read_stop_flag) = client_read_stop_flag {
         client_read_stop_flag.0.store(true, Ordering::Relaxed);
     }
+    if let Some(network_stream) = client_stream
+        .as_ref()
+        .and_then(|client_stream| client_stream.stream.lock().unwrap().take())
+    {
+        let _ = network_stream.tcp_stream.shutdown(Shutdown::Both);
+    }
     Ok(())
 }
 fn reconnect(
@@ -290,56 +492,132 @@ fn setup(client_config: Option<Res<ClientConfig>>, server_config: Option<Res<Ser
 fn send_data_to_clients(
     server_streams: Res<ServerStreams>,
     mut server_data_uploader: ResMut<ServerDataUploader>,
+    server_config: Res<ServerConfig>,
+    network_tick: Res<NetworkTick>,
+    relevant_entities: Res<RelevantEntities>,
+    network_rooms: Res<NetworkRooms>,
 ) {
     let mut owned_list = vec![];
     std::mem::swap(&mut server_data_uploader.list, &mut owned_list);
+    let server_streams = server_streams.streams.lock().unwrap();
+    let mut batches: HashMap<u32, Vec<u8>> = HashMap::new();
     for (recipient, data_packet) in owned_list {
+        let frame = build_frame(data_packet, network_tick.get(), &server_config.compression);
         match recipient {
             Recipient::All => {
-                for (_, stream) in server_streams.streams.lock().unwrap().iter() {
-                    if let Err(err) = send_data(data_packet.clone(), stream) {
-                        println!("Received an error trying to send data packet: {err:#?}");
-                    }
+                for (key, stream) in server_streams.iter() {
+                    queue_frame(
+                        &frame,
+                        stream,
+                        batches.entry(*key).or_default(),
+                        &server_config.batching,
+                        &server_config.socket_options,
+                    );
                 }
             }
             Recipient::AllExcept { id } => {
-                for (key, stream) in server_streams.streams.lock().unwrap().iter() {
+                for (key, stream) in server_streams.iter() {
                     if *key != id {
-                        if let Err(err) = send_data(data_packet.clone(), stream) {
-                            println!("Received an error trying to send data packet: {err:#?}");
-                        }
+                        queue_frame(
+                            &frame,
+                            stream,
+                            batches.entry(*key).or_default(),
+                            &server_config.batching,
+                            &server_config.socket_options,
+                        );
                     }
                 }
             }
-            Recipient::Single { id } => match server_streams.streams.lock().unwrap().get(&id) {
+            Recipient::Single { id } => match server_streams.get(&id) {
                 Some(stream) => {
-                    if let Err(err) = send_data(data_packet, stream) {
-                        println!("Received an error trying to send data packet: {err:#?}");
-                    }
+                    queue_frame(
+                        &frame,
+                        stream,
+                        batches.entry(id).or_default(),
+                        &server_config.batching,
+                        &server_config.socket_options,
+                    );
                 }
                 None => {
                     eprintln!("Couldn't find the id {id}'s corresponding stream to send the data!");
                 }
             },
+            recipient => {
+                for (key, stream) in server_streams.iter() {
+                    if recipient.includes(*key, &relevant_entities, &network_rooms) {
+                        queue_frame(
+                            &frame,
+                            stream,
+                            batches.entry(*key).or_default(),
+                            &server_config.batching,
+                            &server_config.socket_options,
+                        );
+                    }
+                }
+            }
         }
     }
+    for (id, stream) in server_streams.iter() {
+        let mut batch = batches.remove(id).unwrap_or_default();
+        flush_batch(stream, &mut batch, &server_config.socket_options);
+    }
 }
 fn send_data_to_server(
     mut client_data_uploader: ResMut<ClientDataUploader>,
     client_stream: Res<ClientStream>,
+    client_config: Res<ClientConfig>,
+    network_tick: Res<NetworkTick>,
 ) {
     let stream = client_stream.stream.lock().unwrap();
     let stream = stream.as_ref();
     if let Some(stream) = stream {
         let owned_list = client_data_uploader.list.take().unwrap();
         client_data_uploader.list = Some(vec![]);
+        let mut batch = vec![];
         for data_packet in owned_list {
-            if let Err(err) = send_data(data_packet, &stream) {
-                println!("Received an error trying to send data packet: {err:#?}");
-            }
+            let frame = build_frame(data_packet, network_tick.get(), &client_config.compression);
+            queue_frame(
+                &frame,
+                stream,
+                &mut batch,
+                &client_config.batching,
+                &client_config.socket_options,
+            );
         }
+        flush_batch(stream, &mut batch, &client_config.socket_options);
     }
 }
+fn queue_frame(
+    frame: &Frame,
+    stream: &NetworkStream,
+    batch: &mut Vec<u8>,
+    batching: &Batching,
+    socket_options: &SocketOptions,
+) {
+    if let Err(err) = write_frame(frame, stream, batch) {
+        println!("Received an error trying to send data packet: {err:#?}");
+        return;
+    }
+    if let Batching::MaxSize { bytes } = batching {
+        if batch.len() >= *bytes {
+            flush_batch(stream, batch, socket_options);
+        }
+    }
+}
+fn flush_batch(stream: &NetworkStream, batch: &mut Vec<u8>, socket_options: &SocketOptions) {
+    if let Err(err) = socket::write_pending(
+        &stream.tcp_stream,
+        &stream.pending_writes,
+        batch,
+        socket_options,
+    ) {
+        let err = ConnectionError::FailedToSendData(err);
+        println!("Received an error trying to send data packet: {err:#?}. Disconnecting");
+        let _ = stream.tcp_stream.shutdown(Shutdown::Both);
+    }
+    batch.clear();
+}
+#[allow(clippy::too_many_arguments)]
 pub fn connect_to_server(
     mut client_stream: ResMut<ClientStream>,
     mut client_data_uploader: ResMut<ClientDataUploader>,
@@ -347,6 +625,8 @@ pub fn connect_to_server(
     client_config: Option<Res<ClientConfig>>,
     client_data_read_sender: Res<CrossbeamEventSender<ClientDataReadEvent>>,
     server_connection_change_sender: Res<CrossbeamEventSender<ServerConnectionChangeEvent>>,
+    network_stats: Res<NetworkStats>,
+    network_runtime: Res<NetworkRuntime>,
 ) -> Result<(), ConnectionError> {
     match client_config {
         Some(client_config) => match TcpStream::connect(client_config.server_address) {
@@ -354,39 +634,95 @@ pub fn connect_to_server(
                 if client_stream.stream.lock().unwrap().is_some() {
                     return Err(ConnectionError::AlreadyConnectedToServer);
                 } else {
-                    client_stream.stream = Arc::new(Mutex::new(Some(stream.try_clone().unwrap())));
+                    if let Err(err) =
+                        socket::apply_socket_options(&stream, &client_config.socket_options)
+                    {
+                        return Err(ConnectionError::TcpErr(err));
+                    }
+                    client_stream.stream = Arc::new(Mutex::new(None));
                     let client_data_read_sender = client_data_read_sender.clone();
                     let server_connection_change_sender = server_connection_change_sender.clone();
-                    let task_pool = IoTaskPool::get();
                     let client_stream = client_stream.stream.clone();
+                    let client_encryption = client_config.encryption.clone();
+                    let compression = client_config.compression.clone();
+                    let max_frame_size = client_config.max_frame_size;
+                    let handshake_timeout = client_config.handshake_timeout;
+                    let network_stats = network_stats.clone();
                     if let None = client_data_uploader.list {
                         client_data_uploader.list = Some(vec![]);
                     }
                     let stop_flag = Arc::new(AtomicBool::new(false));
                     let stop_flag_clone = stop_flag.clone();
                     commands.insert_resource(ClientReadStopFlag(stop_flag));
-                    server_connection_change_sender.send(ServerConnectionChangeEvent {
-                        connection_change: ConnectionChange::Connected,
-                    });
-                    task_pool
-                        .spawn(async move {
-                            let runtime =
-                                Builder::new_current_thread().enable_all().build().unwrap();
-                            runtime.block_on(async move {
-                                println!("Successfully established connection with server!");
-                                read_stream(
-                                    stream,
-                                    StreamEndpoint::Client {
-                                        client_data_read_sender,
-                                        client_stream,
-                                        server_connection_change_sender,
-                                        stop_flag: stop_flag_clone,
-                                    },
+                    if let ClientEncryption::None = client_encryption {
+                        *client_stream.lock().unwrap() = Some(NetworkStream {
+                            tcp_stream: stream.try_clone().unwrap(),
+                            session: None,
+                            compression: compression.clone(),
+                            stats: network_stats.clone(),
+                            pending_writes: Default::default(),
+                        });
+                        server_connection_change_sender.send(ServerConnectionChangeEvent {
+                            connection_change: ConnectionChange::Connected,
+                        });
+                    }
+                    network_runtime.spawn(async move {
+                        let write_stream = stream.try_clone().unwrap();
+                        let mut tokio_stream = into_tokio_stream(stream);
+                        let stream_endpoint = StreamEndpoint::Client {
+                            client_data_read_sender,
+                            client_stream: client_stream.clone(),
+                            server_connection_change_sender: server_connection_change_sender
+                                .clone(),
+                            stop_flag: stop_flag_clone,
+                        };
+                        let session = match client_encryption {
+                            ClientEncryption::None => None,
+                            ClientEncryption::Noise { pinned_server_key } => {
+                                match encryption::client_handshake(
+                                    &mut tokio_stream,
+                                    pinned_server_key,
+                                    handshake_timeout,
                                 )
-                                .await;
-                            });
-                        })
-                        .detach();
+                                .await
+                                {
+                                    Ok(session) => {
+                                        let session = Arc::new(session);
+                                        *client_stream.lock().unwrap() = Some(NetworkStream {
+                                            tcp_stream: write_stream,
+                                            session: Some(session.clone()),
+                                            compression: compression.clone(),
+                                            stats: network_stats.clone(),
+                                            pending_writes: Default::default(),
+                                        });
+                                        server_connection_change_sender.send(
+                                            ServerConnectionChangeEvent {
+                                                connection_change: ConnectionChange::Connected,
+                                            },
+                                        );
+                                        Some(session)
+                                    }
+                                    Err(err) => {
+                                        eprintln!(
+                                            "Encrypted handshake with the server failed: {err:#?}"
+                                        );
+                                        disconnect(&stream_endpoint);
+                                        return;
+                                    }
+                                }
+                            }
+                        };
+                        println!("Successfully established connection with server!");
+                        read_stream(
+                            tokio_stream,
+                            session,
+                            compression,
+                            max_frame_size,
+                            network_stats,
+                            stream_endpoint,
+                        )
+                        .await;
+                    });
                 }
                 return Ok(());
             }
@@ -405,6 +741,8 @@ pub fn host_server(
     server_config: Option<Res<ServerConfig>>,
     server_data_read_sender: Res<CrossbeamEventSender<ServerDataReadEvent>>,
     player_intergress_sender: Res<CrossbeamEventSender<PlayerIntergressEvent>>,
+    network_stats: Res<NetworkStats>,
+    network_runtime: Res<NetworkRuntime>,
 ) -> Result<(), ConnectionError> {
     match server_config {
         Some(server_config) => {
@@ -412,44 +750,91 @@ pub fn host_server(
                 Ok(listener) => {
                     let server_data_read_sender = server_data_read_sender.clone();
                     let player_intergress_sender = player_intergress_sender.clone();
-                    let task_pool = IoTaskPool::get();
                     let server_streams = server_streams.streams.clone();
-                    task_pool
-                        .spawn(async move {
-                            let mut current_id = 0u32;
-                            let tokio_runtime =
-                                Builder::new_multi_thread().enable_all().build().unwrap();
-                            tokio_runtime.block_on(async move {
-                                for stream in listener.incoming() {
-                                    let stream = stream.unwrap();
-                                    let server_data_read_sender = server_data_read_sender.clone();
-                                    let player_intergress_sender = player_intergress_sender.clone();
-                                    player_intergress_sender.send(PlayerIntergressEvent {
-                                        id: current_id,
-                                        intergress_type: IntergressType::Joined,
-                                    });
-                                    server_streams
-                                        .lock()
-                                        .unwrap()
-                                        .insert(current_id, stream.try_clone().unwrap());
-                                    let server_streams = server_streams.clone();
-                                    tokio::spawn(async move {
-                                        read_stream(
-                                            stream,
-                                            StreamEndpoint::Server {
-                                                id: current_id,
-                                                server_streams,
-                                                server_data_read_sender,
-                                                player_intergress_sender,
-                                            },
-                                        )
-                                        .await;
-                                    });
-                                    current_id += 1;
+                    let server_encryption = server_config.encryption.clone();
+                    let compression = server_config.compression.clone();
+                    let max_frame_size = server_config.max_frame_size;
+                    let handshake_timeout = server_config.handshake_timeout;
+                    let socket_options = server_config.socket_options.clone();
+                    let network_stats = network_stats.clone();
+                    let network_tasks = network_runtime.tasks();
+                    if let Err(err) = listener.set_nonblocking(true) {
+                        return Err(ConnectionError::TcpErr(err));
+                    }
+                    network_runtime.spawn(async move {
+                        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                        let mut current_id = 0u32;
+                        loop {
+                            let stream = match listener.accept().await {
+                                Ok((stream, _)) => stream.into_std().unwrap(),
+                                Err(err) => {
+                                    eprintln!("Failed to accept an incoming connection: {err:#?}");
+                                    continue;
                                 }
+                            };
+                            if let Err(err) = socket::apply_socket_options(&stream, &socket_options)
+                            {
+                                eprintln!("Couldn't apply the socket options to client id {current_id}'s stream: {err:#?}");
+                            }
+                            let server_data_read_sender = server_data_read_sender.clone();
+                            let player_intergress_sender = player_intergress_sender.clone();
+                            let server_streams = server_streams.clone();
+                            let server_encryption = server_encryption.clone();
+                            let compression = compression.clone();
+                            let network_stats = network_stats.clone();
+                            network_tasks.spawn(async move {
+                                let write_stream = stream.try_clone().unwrap();
+                                let mut tokio_stream = into_tokio_stream(stream);
+                                let session = match server_encryption {
+                                    ServerEncryption::None => None,
+                                    ServerEncryption::Noise { keypair } => {
+                                        match encryption::server_handshake(
+                                            &mut tokio_stream,
+                                            &keypair,
+                                            handshake_timeout,
+                                        )
+                                        .await
+                                        {
+                                            Ok(session) => Some(Arc::new(session)),
+                                            Err(err) => {
+                                                eprintln!("Encrypted handshake with client id {current_id} failed: {err:#?}");
+                                                return;
+                                            }
+                                        }
+                                    }
+                                };
+                                player_intergress_sender.send(PlayerIntergressEvent {
+                                    id: current_id,
+                                    intergress_type: IntergressType::Joined,
+                                });
+                                server_streams.lock().unwrap().insert(
+                                    current_id,
+                                    NetworkStream {
+                                        tcp_stream: write_stream,
+                                        session: session.clone(),
+                                        compression: compression.clone(),
+                                        stats: network_stats.clone(),
+                                        pending_writes: Default::default(),
+                                    },
+                                );
+                                read_stream(
+                                    tokio_stream,
+                                    session,
+                                    compression,
+                                    max_frame_size,
+                                    network_stats,
+                                    StreamEndpoint::Server {
+                                        id: current_id,
+                                        server_streams,
+                                        server_data_read_sender,
+                                        player_intergress_sender,
+                                    },
+                                )
+                                .await;
                             });
-                        })
-                        .detach();
+                            current_id += 1;
+                        }
+                    });
                     return Ok(());
                 }
                 Err(err) => return Err(ConnectionError::TcpErr(err)),
@@ -458,40 +843,55 @@ pub fn host_server(
         None => return Err(ConnectionError::MissingServerConfig),
     }
 }
+fn into_tokio_stream(stream: TcpStream) -> tokio::net::TcpStream {
+    stream
+        .set_nonblocking(true)
+        .expect("set_nonblocking call failed");
+    tokio::net::TcpStream::from_std(stream).unwrap()
+}
+fn disconnect(stream_endpoint: &StreamEndpoint) {
+    match stream_endpoint {
+        StreamEndpoint::Client {
+            client_stream,
+            server_connection_change_sender,
+            ..
+        } => {
+            *client_stream.lock().unwrap() = None;
+            server_connection_change_sender.send(ServerConnectionChangeEvent {
+                connection_change: ConnectionChange::Disconnected,
+            });
+        }
+        StreamEndpoint::Server {
+            id,
+            ref server_streams,
+            player_intergress_sender,
+            ..
+        } => {
+            match server_streams.lock().unwrap().remove_entry(id) {
+                Some(_) => println!("Removed stream with id: {id}"),
+                None => println!("No entry with id: {id} was found"),
+            }
+            player_intergress_sender.send(PlayerIntergressEvent {
+                id: *id,
+                intergress_type: IntergressType::Left,
+            });
+        }
+    }
+}
 fn handle_stream_error(
     error: std::io::Error,
     stream_endpoint: &StreamEndpoint,
 ) -> Result<(), ConnectionError> {
     match error.kind() {
-        std::io::ErrorKind::ConnectionReset => {
-            match stream_endpoint {
-                StreamEndpoint::Client {
-                    client_stream,
-                    server_connection_change_sender,
-                    ..
-                } => {
-                    *client_stream.lock().unwrap() = None;
-                    server_connection_change_sender.send(ServerConnectionChangeEvent {
-                        connection_change: ConnectionChange::Disconnected,
-                    });
-                }
-                StreamEndpoint::Server {
-                    id,
-                    ref server_streams,
-                    player_intergress_sender,
-                    ..
-                } => {
-                    println!("Client with id {id}'s connection has been reset. Removing from stream hashmap");
-                    match server_streams.lock().unwrap().remove_entry(id) {
-                        Some(_) => println!("Removed stream with id: {id}"),
-                        None => println!("No entry with id: {id} was found"),
-                    }
-                    player_intergress_sender.send(PlayerIntergressEvent {
-                        id: *id,
-                        intergress_type: IntergressType::Left,
-                    });
-                }
+        std::io::ErrorKind::ConnectionReset
+        | std::io::ErrorKind::ConnectionAborted
+        | std::io::ErrorKind::UnexpectedEof => {
+            if let StreamEndpoint::Server { id, .. } = stream_endpoint {
+                println!(
+                    "Client with id {id}'s connection has been reset. Removing from stream hashmap"
+                );
             }
+            disconnect(stream_endpoint);
             return Err(ConnectionError::ConnectionReset);
         }
         _ => {
@@ -500,7 +900,12 @@ fn handle_stream_error(
         }
     }
 }
-fn send_data_read_event(identifier: u32, bytes: Vec<u8>, stream_endpoint: &StreamEndpoint) {
+fn send_data_read_event(
+    identifier: u32,
+    tick: u32,
+    bytes: Bytes,
+    stream_endpoint: &StreamEndpoint,
+) {
     match stream_endpoint {
         StreamEndpoint::Server {
             id,
@@ -510,6 +915,7 @@ fn send_data_read_event(identifier: u32, bytes: Vec<u8>, stream_endpoint: &Strea
             server_data_read_sender.send(ServerDataReadEvent {
                 data_packet: DataPacket { identifier, bytes },
                 id: *id,
+                tick,
             });
         }
         StreamEndpoint::Client {
@@ -518,19 +924,22 @@ fn send_data_read_event(identifier: u32, bytes: Vec<u8>, stream_endpoint: &Strea
         } => {
             client_data_read_sender.send(ClientDataReadEvent {
                 data_packet: DataPacket { identifier, bytes },
+                tick,
             });
         }
     }
 }
-async fn read_stream(stream: TcpStream, stream_endpoint: StreamEndpoint) {
-    stream
-        .set_nonblocking(true)
-        .expect("set_nonblocking call failed");
-    let tokio_stream = tokio::net::TcpStream::from_std(stream).unwrap();
+async fn read_stream(
+    tokio_stream: tokio::net::TcpStream,
+    session: Option<Arc<EncryptedSession>>,
+    compression: Compression,
+    max_frame_size: usize,
+    network_stats: NetworkStats,
+    stream_endpoint: StreamEndpoint,
+) {
     let mut buf_reader = tokio::io::BufReader::new(tokio_stream);
-    let mut header = [0u8; 8];
-    let mut data_chunk = [0u8; CHUNK_SIZE];
-    let mut bytes_left: i32;
+    let mut header = [0u8; HEADER_SIZE];
+    let mut read_buffer = BytesMut::new();
     loop {
         if let StreamEndpoint::Client {
             ref stop_flag,
@@ -545,7 +954,59 @@ async fn read_stream(stream: TcpStream, stream_endpoint: StreamEndpoint) {
                 break;
             }
         }
-        let mut data: Vec<u8> = Vec::new();
+        if let Some(session) = &session {
+            let mut sealed_size = [0u8; 4];
+            if let Err(err) = buf_reader.read_exact(&mut sealed_size).await {
+                if let Err(ConnectionError::ConnectionReset) =
+                    handle_stream_error(err, &stream_endpoint)
+                {
+                    return;
+                }
+                continue;
+            }
+            let sealed_size = combine_u8s_into_u32(sealed_size) as usize;
+            let max_sealed_size = encryption::max_sealed_size(HEADER_SIZE + max_frame_size);
+            if sealed_size > max_sealed_size {
+                eprintln!(
+                    "Received an encrypted frame of {sealed_size} bytes, which is larger than the maximum of {max_sealed_size} bytes. Disconnecting"
+                );
+                disconnect(&stream_endpoint);
+                return;
+            }
+            let sealed = match read_bytes(&mut buf_reader, &mut read_buffer, sealed_size).await {
+                Ok(sealed) => sealed,
+                Err(err) => {
+                    if let Err(ConnectionError::ConnectionReset) =
+                        handle_stream_error(err, &stream_endpoint)
+                    {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            match session
+                .open(&sealed)
+                .and_then(|frame| split_frame(Bytes::from(frame)))
+            {
+                Ok((identifier, flags, tick, data)) => receive_frame(
+                    identifier,
+                    flags,
+                    tick,
+                    data,
+                    sealed_size + 4,
+                    &compression,
+                    max_frame_size,
+                    &network_stats,
+                    &stream_endpoint,
+                ),
+                Err(err) => {
+                    eprintln!("Received a frame that could not be decrypted: {err:#?}");
+                    disconnect(&stream_endpoint);
+                    return;
+                }
+            }
+            continue;
+        }
         if let Err(err) = buf_reader.read_exact(&mut header).await {
             if let Err(err) = handle_stream_error(err, &stream_endpoint) {
                 if let ConnectionError::ConnectionReset = err {
@@ -554,67 +1015,160 @@ async fn read_stream(stream: TcpStream, stream_endpoint: StreamEndpoint) {
             }
             continue;
         }
-        let (bytes_left_arr, identifier_arr) = header.split_at(4);
-        bytes_left = combine_u8s_into_u32(bytes_left_arr.try_into().unwrap()) as i32;
-        let identifier = combine_u8s_into_u32(identifier_arr.try_into().unwrap());
-        loop {
-            let bytes = if bytes_left >= CHUNK_SIZE as i32 {
-                match buf_reader.read_exact(&mut data_chunk).await {
-                    Ok(_) => CHUNK_SIZE,
-                    Err(err) => {
-                        if let Err(err) = handle_stream_error(err, &stream_endpoint) {
-                            if let ConnectionError::ConnectionReset = err {
-                                return;
-                            }
-                        }
-                        continue;
-                    }
-                }
-            } else {
-                match buf_reader
-                    .read_exact(&mut data_chunk[0..bytes_left as usize])
-                    .await
+        let (packet_size, identifier, flags, tick) = parse_header(&header);
+        if packet_size as usize > max_frame_size {
+            eprintln!(
+                "Received a frame of {packet_size} bytes, which is larger than the maximum of {max_frame_size} bytes. Disconnecting"
+            );
+            disconnect(&stream_endpoint);
+            return;
+        }
+        let data = match read_bytes(&mut buf_reader, &mut read_buffer, packet_size as usize).await {
+            Ok(data) => data,
+            Err(err) => {
+                if let Err(ConnectionError::ConnectionReset) =
+                    handle_stream_error(err, &stream_endpoint)
                 {
-                    Ok(bytes) => bytes,
-                    Err(err) => {
-                        if let Err(err) = handle_stream_error(err, &stream_endpoint) {
-                            if let ConnectionError::ConnectionReset = err {
-                                return;
-                            }
-                        }
-                        continue;
-                    }
+                    return;
                 }
-            };
-            data.extend_from_slice(&data_chunk[0..bytes]);
-            bytes_left -= bytes as i32;
-            if bytes_left == 0 {
-                break;
-            } else if bytes_left < 0 {
-                panic!("Data bytes left was under 0: {}", bytes_left);
+                continue;
             }
+        };
+        receive_frame(
+            identifier,
+            flags,
+            tick,
+            data,
+            HEADER_SIZE + packet_size as usize,
+            &compression,
+            max_frame_size,
+            &network_stats,
+            &stream_endpoint,
+        );
+    }
+}
+async fn read_bytes(
+    buf_reader: &mut tokio::io::BufReader<tokio::net::TcpStream>,
+    read_buffer: &mut BytesMut,
+    size: usize,
+) -> std::io::Result<Bytes> {
+    read_buffer.clear();
+    read_buffer.resize(size, 0);
+    buf_reader.read_exact(&mut read_buffer[..]).await?;
+    Ok(read_buffer.split().freeze())
+}
+#[allow(clippy::too_many_arguments)]
+fn receive_frame(
+    identifier: u32,
+    flags: u8,
+    tick: u32,
+    data: Bytes,
+    wire_size: usize,
+    compression: &Compression,
+    max_frame_size: usize,
+    network_stats: &NetworkStats,
+    stream_endpoint: &StreamEndpoint,
+) {
+    let compressed_size = data.len();
+    match compression.decompress(flags, data, max_frame_size) {
+        Ok(data) => {
+            let compression_sizes = if flags == compression::UNCOMPRESSED {
+                None
+            } else {
+                Some((data.len(), compressed_size))
+            };
+            network_stats.record_received(wire_size, compression_sizes);
+            send_data_read_event(identifier, tick, data, stream_endpoint);
+        }
+        Err(err) => {
+            eprintln!("Dropped a packet with identifier {identifier} that could not be decompressed: {err:#?}");
         }
-        send_data_read_event(identifier, data, &stream_endpoint);
     }
 }
-pub fn send_data(data_packet: DataPacket, mut stream: &TcpStream) -> Result<(), ConnectionError> {
-    let bytes = data_packet.bytes;
+pub fn send_data(
+    data_packet: DataPacket,
+    tick: u32,
+    stream: &NetworkStream,
+) -> Result<(), ConnectionError> {
+    let frame = build_frame(data_packet, tick, &stream.compression);
+    let mut packet: Vec<u8> = vec![];
+    write_frame(&frame, stream, &mut packet)?;
+    if let Err(err) = socket::write_pending(
+        &stream.tcp_stream,
+        &stream.pending_writes,
+        &packet,
+        &SocketOptions::default(),
+    ) {
+        return Err(ConnectionError::FailedToSendData(err));
+    }
+    Ok(())
+}
+struct Frame {
+    bytes: Bytes,
+    compression_sizes: Option<(usize, usize)>,
+}
+fn build_frame(data_packet: DataPacket, tick: u32, compression: &Compression) -> Frame {
     let identifier = data_packet.identifier;
+    let uncompressed_size = data_packet.bytes.len();
+    let (flags, bytes, compression_sizes) = match compression.compress(&data_packet.bytes) {
+        Some((flags, compressed)) => {
+            let compressed_size = compressed.len();
+            (
+                flags,
+                Bytes::from(compressed),
+                Some((uncompressed_size, compressed_size)),
+            )
+        }
+        None => (compression::UNCOMPRESSED, data_packet.bytes, None),
+    };
     let packet_size = bytes.len() as u32;
     let packet_size_bytes = split_u32_into_u8s(packet_size);
-    let mut packet: Vec<u8> = vec![];
-    for byte in packet_size_bytes {
-        packet.push(byte);
+    let mut packet = BytesMut::with_capacity(HEADER_SIZE + bytes.len());
+    packet.extend_from_slice(&packet_size_bytes);
+    packet.extend_from_slice(&split_u32_into_u8s(identifier));
+    packet.extend_from_slice(&[flags]);
+    packet.extend_from_slice(&split_u32_into_u8s(tick));
+    packet.extend_from_slice(&bytes);
+    Frame {
+        bytes: packet.freeze(),
+        compression_sizes,
     }
-    let identifier_bytes = split_u32_into_u8s(identifier);
-    for byte in identifier_bytes {
-        packet.push(byte);
+}
+fn write_frame(
+    frame: &Frame,
+    stream: &NetworkStream,
+    buffer: &mut Vec<u8>,
+) -> Result<(), ConnectionError> {
+    let wire_size = match &stream.session {
+        Some(session) => {
+            let sealed = session.seal(&frame.bytes)?;
+            buffer.extend_from_slice(&sealed);
+            sealed.len()
+        }
+        None => {
+            buffer.extend_from_slice(&frame.bytes);
+            frame.bytes.len()
+        }
+    };
+    stream.stats.record_sent(wire_size, frame.compression_sizes);
+    Ok(())
+}
+fn parse_header(header: &[u8]) -> (u32, u32, u8, u32) {
+    let packet_size = combine_u8s_into_u32(header[0..4].try_into().unwrap());
+    let identifier = combine_u8s_into_u32(header[4..8].try_into().unwrap());
+    let tick = combine_u8s_into_u32(header[9..13].try_into().unwrap());
+    (packet_size, identifier, header[8], tick)
+}
+fn split_frame(mut frame: Bytes) -> Result<(u32, u8, u32, Bytes), ConnectionError> {
+    if frame.len() < HEADER_SIZE {
+        return Err(ConnectionError::InvalidFrame);
     }
-    packet.extend_from_slice(&bytes[..]);
-    if let Err(err) = stream.write_all(&packet) {
-        return Err(ConnectionError::FailedToSendData(err));
+    let bytes = frame.split_off(HEADER_SIZE);
+    let (packet_size, identifier, flags, tick) = parse_header(&frame);
+    if packet_size as usize != bytes.len() {
+        return Err(ConnectionError::InvalidFrame);
     }
-    Ok(())
+    Ok((identifier, flags, tick, bytes))
 }
 fn split_u32_into_u8s(input: u32) -> [u8; 4] {
     let byte1 = (input >> 24) as u8;
@@ -630,3 +1184,133 @@ fn combine_u8s_into_u32(bytes: [u8; 4]) -> u32 {
     let byte4 = bytes[3] as u32;
     byte1 | byte2 | byte3 | byte4
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    const MAX_FRAME_SIZE: usize = 1024;
+    fn frame(size: usize) -> Bytes {
+        let data_packet = DataPacket {
+            identifier: 3,
+            bytes: vec![7; size].into(),
+        };
+        build_frame(data_packet, 0, &Compression::None).bytes
+    }
+    // Writes the frames to a local connection and returns whether the reader gave up on it
+    // along with what it received
+    fn read_frames(frames: &[Bytes], encrypted: bool) -> (bool, Vec<DataPacket>, usize) {
+        let mut app = App::new();
+        app.add_crossbeam_event::<ServerDataReadEvent>()
+            .add_crossbeam_event::<PlayerIntergressEvent>();
+        let stream_endpoint = StreamEndpoint::Server {
+            id: 0,
+            server_streams: Default::default(),
+            server_data_read_sender: app
+                .world()
+                .resource::<CrossbeamEventSender<ServerDataReadEvent>>()
+                .clone(),
+            player_intergress_sender: app
+                .world()
+                .resource::<CrossbeamEventSender<PlayerIntergressEvent>>()
+                .clone(),
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let stopped = runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut writer = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+                .await
+                .unwrap();
+            let (mut reader, _) = listener.accept().await.unwrap();
+            let mut session = None;
+            let mut bytes = vec![];
+            if encrypted {
+                let keypair = EncryptionKeypair::generate();
+                let timeout = Duration::from_secs(1);
+                let server = tokio::spawn(async move {
+                    let session =
+                        encryption::server_handshake(&mut reader, &keypair, timeout).await;
+                    (reader, session)
+                });
+                let client = encryption::client_handshake(&mut writer, None, timeout)
+                    .await
+                    .unwrap();
+                let (server_reader, server) = server.await.unwrap();
+                reader = server_reader;
+                for frame in frames {
+                    bytes.extend_from_slice(&client.seal(frame).unwrap());
+                }
+                session = Some(Arc::new(server.unwrap()));
+            } else {
+                for frame in frames {
+                    bytes.extend_from_slice(frame);
+                }
+            }
+            writer.write_all(&bytes).await.unwrap();
+            let stopped = tokio::time::timeout(
+                Duration::from_millis(500),
+                read_stream(
+                    reader,
+                    session,
+                    Compression::None,
+                    MAX_FRAME_SIZE,
+                    NetworkStats::default(),
+                    stream_endpoint,
+                ),
+            )
+            .await
+            .is_ok();
+            drop(writer);
+            stopped
+        });
+        app.update();
+        let world = app.world_mut();
+        let data_packets = world
+            .resource_mut::<Events<ServerDataReadEvent>>()
+            .drain()
+            .map(|event| event.data_packet)
+            .collect();
+        let left = world
+            .resource_mut::<Events<PlayerIntergressEvent>>()
+            .drain()
+            .filter(|event| matches!(event.intergress_type, IntergressType::Left))
+            .count();
+        (stopped, data_packets, left)
+    }
+    #[test]
+    fn accepts_frames_up_to_max_frame_size() {
+        for encrypted in [false, true] {
+            let (stopped, data_packets, left) =
+                read_frames(&[frame(0), frame(MAX_FRAME_SIZE)], encrypted);
+            assert!(!stopped);
+            assert_eq!(left, 0);
+            let sizes: Vec<usize> = data_packets
+                .iter()
+                .map(|data_packet| data_packet.bytes.len())
+                .collect();
+            assert_eq!(sizes, vec![0, MAX_FRAME_SIZE]);
+        }
+    }
+    #[test]
+    fn disconnects_on_oversized_frames() {
+        for encrypted in [false, true] {
+            let (stopped, data_packets, left) =
+                read_frames(&[frame(1), frame(MAX_FRAME_SIZE + 1), frame(2)], encrypted);
+            assert!(stopped);
+            assert_eq!(left, 1);
+            assert_eq!(data_packets.len(), 1);
+            assert_eq!(data_packets[0].bytes.len(), 1);
+        }
+    }
+    #[test]
+    fn disconnects_on_oversized_headers_before_reading_them() {
+        let mut header = frame(0).to_vec();
+        header[..4].copy_from_slice(&split_u32_into_u8s(u32::MAX));
+        let (stopped, data_packets, left) = read_frames(&[header.into()], false);
+        assert!(stopped);
+        assert_eq!(left, 1);
+        assert!(data_packets.is_empty());
+    }
+}
@@ -0,0 +1,418 @@
+use crate::{
+    has_server_config, IntergressType, NetworkDirection, NetworkEventApp, PlayerIntergressEvent,
+    Recipient, Rpc, RpcApp, ToClients,
+};
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+use snow::{
+    params::HashChoice,
+    resolvers::{CryptoResolver, DefaultResolver},
+};
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LobbyId(pub u32);
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LobbyPlayer {
+    pub id: u32,
+    pub ready: bool,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LobbyInfo {
+    pub id: LobbyId,
+    pub name: String,
+    pub host: u32,
+    pub slots: u32,
+    pub players: Vec<LobbyPlayer>,
+    pub metadata: HashMap<String, String>,
+}
+impl LobbyInfo {
+    pub fn is_full(&self) -> bool {
+        self.players.len() >= self.slots as usize
+    }
+    pub fn all_ready(&self) -> bool {
+        self.players.iter().all(|player| player.ready)
+    }
+    fn recipient(&self) -> Recipient {
+        Recipient::Many(self.players.iter().map(|player| player.id).collect())
+    }
+}
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum LobbyRequest {
+    List,
+    Create {
+        name: String,
+        slots: u32,
+        metadata: HashMap<String, String>,
+    },
+    Join {
+        lobby: LobbyId,
+    },
+    Leave,
+    SetReady {
+        ready: bool,
+    },
+    SetMetadata {
+        key: String,
+        value: String,
+    },
+    Start,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum LobbyResponse {
+    Lobbies(Vec<LobbyInfo>),
+    Joined(LobbyInfo),
+    Updated(LobbyInfo),
+    Left,
+    Started,
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LobbyError {
+    NotFound,
+    Full,
+    InvalidSlots,
+    AlreadyInLobby,
+    NotInLobby,
+    NotHost,
+    NotReady,
+    Unavailable,
+}
+impl Rpc for LobbyRequest {
+    type Response = Result<LobbyResponse, LobbyError>;
+}
+#[derive(Event, Serialize, Deserialize, Clone, Debug)]
+pub enum LobbyEvent {
+    Updated(LobbyInfo),
+    Started {
+        address: SocketAddr,
+        ticket: LobbyTicket,
+    },
+}
+#[derive(Event, Clone, Debug)]
+pub struct LobbyStarted {
+    pub lobby: LobbyInfo,
+    pub address: SocketAddr,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LobbyTicket {
+    pub lobby: LobbyId,
+    pub player: u32,
+    pub issued_at: u64,
+    mac: Vec<u8>,
+}
+impl LobbyTicket {
+    fn issue(secret: &[u8; 32], lobby: LobbyId, player: u32) -> LobbyTicket {
+        let issued_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        LobbyTicket {
+            lobby,
+            player,
+            issued_at,
+            mac: sign_ticket(secret, lobby, player, issued_at),
+        }
+    }
+    pub fn verify(&self, secret: &[u8; 32], max_age: Duration) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let expected = sign_ticket(secret, self.lobby, self.player, self.issued_at);
+        let matches = expected.len() == self.mac.len()
+            && expected
+                .iter()
+                .zip(self.mac.iter())
+                .fold(0, |difference, (a, b)| difference | (a ^ b))
+                == 0;
+        matches && now.saturating_sub(self.issued_at) <= max_age.as_secs()
+    }
+}
+fn sign_ticket(secret: &[u8; 32], lobby: LobbyId, player: u32, issued_at: u64) -> Vec<u8> {
+    let mut hash = DefaultResolver
+        .resolve_hash(&HashChoice::SHA256)
+        .expect("The default resolver always supports SHA256");
+    let mut data = vec![];
+    data.extend_from_slice(&lobby.0.to_le_bytes());
+    data.extend_from_slice(&player.to_le_bytes());
+    data.extend_from_slice(&issued_at.to_le_bytes());
+    let mut mac = vec![0; hash.hash_len()];
+    hash.hmac(secret, &data, &mut mac);
+    mac
+}
+#[derive(Resource, Clone)]
+pub struct LobbyConfig {
+    pub game_server_address: SocketAddr,
+    pub secret: [u8; 32],
+    pub require_ready: bool,
+}
+#[derive(Resource, Default)]
+pub struct Lobbies {
+    lobbies: BTreeMap<LobbyId, LobbyInfo>,
+    players: HashMap<u32, LobbyId>,
+    next_id: u32,
+}
+impl Lobbies {
+    pub fn get(&self, lobby: LobbyId) -> Option<&LobbyInfo> {
+        self.lobbies.get(&lobby)
+    }
+    pub fn lobby_of(&self, id: u32) -> Option<&LobbyInfo> {
+        self.players
+            .get(&id)
+            .and_then(|lobby| self.lobbies.get(lobby))
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &LobbyInfo> {
+        self.lobbies.values()
+    }
+    fn remove_player(&mut self, id: u32) -> Option<LobbyInfo> {
+        let lobby_id = self.players.remove(&id)?;
+        let lobby = self.lobbies.get_mut(&lobby_id)?;
+        lobby.players.retain(|player| player.id != id);
+        let Some(first) = lobby.players.first() else {
+            self.lobbies.remove(&lobby_id);
+            return None;
+        };
+        if lobby.host == id {
+            lobby.host = first.id;
+        }
+        Some(lobby.clone())
+    }
+}
+pub struct LobbyPlugin;
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Lobbies>()
+            .add_event::<LobbyStarted>()
+            .rpc::<LobbyRequest>()
+            .rpc_handler(handle_lobby_request)
+            .add_network_event::<LobbyEvent>(NetworkDirection::ServerToClient)
+            .add_systems(PreUpdate, remove_left_players.run_if(has_server_config));
+    }
+}
+fn handle_lobby_request(
+    In((id, request)): In<(u32, LobbyRequest)>,
+    mut lobbies: ResMut<Lobbies>,
+    mut to_clients_writer: EventWriter<ToClients<LobbyEvent>>,
+    mut lobby_started_writer: EventWriter<LobbyStarted>,
+    lobby_config: Option<Res<LobbyConfig>>,
+) -> Result<LobbyResponse, LobbyError> {
+    let lobbies = lobbies.as_mut();
+    let lobby_id = lobbies.players.get(&id).copied();
+    let updated = match request {
+        LobbyRequest::List => {
+            return Ok(LobbyResponse::Lobbies(
+                lobbies.lobbies.values().cloned().collect(),
+            ));
+        }
+        LobbyRequest::Create {
+            name,
+            slots,
+            metadata,
+        } => {
+            if lobby_id.is_some() {
+                return Err(LobbyError::AlreadyInLobby);
+            }
+            if slots == 0 {
+                return Err(LobbyError::InvalidSlots);
+            }
+            let lobby = LobbyInfo {
+                id: LobbyId(lobbies.next_id),
+                name,
+                host: id,
+                slots,
+                players: vec![LobbyPlayer { id, ready: false }],
+                metadata,
+            };
+            lobbies.next_id = lobbies.next_id.wrapping_add(1);
+            lobbies.players.insert(id, lobby.id);
+            lobbies.lobbies.insert(lobby.id, lobby.clone());
+            return Ok(LobbyResponse::Joined(lobby));
+        }
+        LobbyRequest::Join { lobby } => {
+            if lobby_id.is_some() {
+                return Err(LobbyError::AlreadyInLobby);
+            }
+            let joined = lobbies
+                .lobbies
+                .get_mut(&lobby)
+                .ok_or(LobbyError::NotFound)?;
+            if joined.is_full() {
+                return Err(LobbyError::Full);
+            }
+            joined.players.push(LobbyPlayer { id, ready: false });
+            let joined = joined.clone();
+            lobbies.players.insert(id, lobby);
+            to_clients_writer.send(ToClients {
+                event: LobbyEvent::Updated(joined.clone()),
+                recipient: joined.recipient(),
+                target: None,
+            });
+            return Ok(LobbyResponse::Joined(joined));
+        }
+        LobbyRequest::Leave => {
+            if lobby_id.is_none() {
+                return Err(LobbyError::NotInLobby);
+            }
+            if let Some(lobby) = lobbies.remove_player(id) {
+                to_clients_writer.send(ToClients {
+                    event: LobbyEvent::Updated(lobby.clone()),
+                    recipient: lobby.recipient(),
+                    target: None,
+                });
+            }
+            return Ok(LobbyResponse::Left);
+        }
+        LobbyRequest::SetReady { ready } => {
+            let lobby = lobby_id
+                .and_then(|lobby_id| lobbies.lobbies.get_mut(&lobby_id))
+                .ok_or(LobbyError::NotInLobby)?;
+            for player in lobby.players.iter_mut() {
+                if player.id == id {
+                    player.ready = ready;
+                }
+            }
+            lobby.clone()
+        }
+        LobbyRequest::SetMetadata { key, value } => {
+            let lobby = lobby_id
+                .and_then(|lobby_id| lobbies.lobbies.get_mut(&lobby_id))
+                .ok_or(LobbyError::NotInLobby)?;
+            if lobby.host != id {
+                return Err(LobbyError::NotHost);
+            }
+            lobby.metadata.insert(key, value);
+            lobby.clone()
+        }
+        LobbyRequest::Start => {
+            let lobby_id = lobby_id.ok_or(LobbyError::NotInLobby)?;
+            let lobby = lobbies
+                .lobbies
+                .get(&lobby_id)
+                .ok_or(LobbyError::NotInLobby)?;
+            if lobby.host != id {
+                return Err(LobbyError::NotHost);
+            }
+            let lobby_config = lobby_config.ok_or(LobbyError::Unavailable)?;
+            if lobby_config.require_ready && !lobby.all_ready() {
+                return Err(LobbyError::NotReady);
+            }
+            let lobby = lobbies
+                .lobbies
+                .remove(&lobby_id)
+                .ok_or(LobbyError::NotFound)?;
+            for player in lobby.players.iter() {
+                lobbies.players.remove(&player.id);
+                to_clients_writer.send(ToClients {
+                    event: LobbyEvent::Started {
+                        address: lobby_config.game_server_address,
+                        ticket: LobbyTicket::issue(&lobby_config.secret, lobby.id, player.id),
+                    },
+                    recipient: Recipient::Single { id: player.id },
+                    target: None,
+                });
+            }
+            lobby_started_writer.send(LobbyStarted {
+                lobby,
+                address: lobby_config.game_server_address,
+            });
+            return Ok(LobbyResponse::Started);
+        }
+    };
+    to_clients_writer.send(ToClients {
+        event: LobbyEvent::Updated(updated.clone()),
+        recipient: updated.recipient(),
+        target: None,
+    });
+    Ok(LobbyResponse::Updated(updated))
+}
+fn remove_left_players(
+    mut player_intergress_reader: EventReader<PlayerIntergressEvent>,
+    mut lobbies: ResMut<Lobbies>,
+    mut to_clients_writer: EventWriter<ToClients<LobbyEvent>>,
+) {
+    for event in player_intergress_reader.read() {
+        let IntergressType::Left = event.intergress_type else {
+            continue;
+        };
+        if let Some(lobby) = lobbies.remove_player(event.id) {
+            to_clients_writer.send(ToClients {
+                event: LobbyEvent::Updated(lobby.clone()),
+                recipient: lobby.recipient(),
+                target: None,
+            });
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const SECRET: [u8; 32] = [7; 32];
+    const MAX_AGE: Duration = Duration::from_secs(60);
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+    #[test]
+    fn issued_tickets_verify() {
+        let ticket = LobbyTicket::issue(&SECRET, LobbyId(3), 9);
+        assert_eq!(ticket.lobby, LobbyId(3));
+        assert_eq!(ticket.player, 9);
+        assert!(ticket.verify(&SECRET, MAX_AGE));
+        assert!(!ticket.verify(&[8; 32], MAX_AGE));
+    }
+    #[test]
+    fn tampered_tickets_are_rejected() {
+        let ticket = LobbyTicket::issue(&SECRET, LobbyId(3), 9);
+        let tampered = [
+            LobbyTicket {
+                lobby: LobbyId(4),
+                ..ticket.clone()
+            },
+            LobbyTicket {
+                player: 10,
+                ..ticket.clone()
+            },
+            LobbyTicket {
+                issued_at: ticket.issued_at + 1,
+                ..ticket.clone()
+            },
+        ];
+        for ticket in tampered {
+            assert!(!ticket.verify(&SECRET, MAX_AGE));
+        }
+        let mut flipped = ticket.clone();
+        flipped.mac[0] ^= 1;
+        assert!(!flipped.verify(&SECRET, MAX_AGE));
+        let mut truncated = ticket.clone();
+        truncated.mac.pop();
+        assert!(!truncated.verify(&SECRET, MAX_AGE));
+        let mut empty = ticket;
+        empty.mac.clear();
+        assert!(!empty.verify(&SECRET, MAX_AGE));
+    }
+    #[test]
+    fn old_tickets_expire() {
+        let issued_at = now() - 120;
+        let ticket = LobbyTicket {
+            lobby: LobbyId(3),
+            player: 9,
+            issued_at,
+            mac: sign_ticket(&SECRET, LobbyId(3), 9, issued_at),
+        };
+        assert!(!ticket.verify(&SECRET, MAX_AGE));
+        assert!(ticket.verify(&SECRET, Duration::from_secs(180)));
+    }
+    #[test]
+    fn tickets_survive_serialization() {
+        let ticket = LobbyTicket::issue(&SECRET, LobbyId(3), 9);
+        let bytes = bincode::serialize(&ticket).unwrap();
+        let received: LobbyTicket = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(received, ticket);
+        assert!(received.verify(&SECRET, MAX_AGE));
+    }
+}
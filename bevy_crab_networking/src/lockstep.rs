@@ -0,0 +1,428 @@
+use crate::{
+    has_client_config, has_server_config, ClientDataReadEvent, ClientDataUploader,
+    ConnectionChange, Identify, IntergressType, NetworkInput, NetworkInputData,
+    PlayerIntergressEvent, Recipient, ServerConnectionChangeEvent, ServerDataReadEvent,
+    ServerDataUploader, LOCKSTEP_IDENTIFIER,
+};
+use bevy::{
+    ecs::schedule::{InternedScheduleLabel, ScheduleLabel},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+const MAX_STEPS_PER_TICK: usize = 2;
+const CHECKSUM_HISTORY: u32 = 64;
+const MAX_STEPS_AHEAD: u32 = 8;
+#[derive(Resource)]
+pub struct LockstepConfig {
+    pub input_delay: u32,
+    pub checksum_interval: u32,
+}
+impl Default for LockstepConfig {
+    fn default() -> Self {
+        LockstepConfig {
+            input_delay: 3,
+            checksum_interval: 1,
+        }
+    }
+}
+#[derive(Resource, Clone, Debug)]
+pub struct LockstepInputs<I> {
+    pub step: u32,
+    pub local_player: u32,
+    pub inputs: Vec<(u32, I)>,
+}
+impl<I> LockstepInputs<I> {
+    pub fn get(&self, player: u32) -> Option<&I> {
+        self.inputs
+            .iter()
+            .find(|(id, _)| *id == player)
+            .map(|(_, input)| input)
+    }
+}
+#[derive(Event, Clone, Debug)]
+pub struct LockstepDesync {
+    pub step: u32,
+    pub checksums: Vec<(u32, u64)>,
+}
+pub trait LockstepApp {
+    fn lockstep<I: NetworkInputData>(&mut self, schedule: impl ScheduleLabel) -> &mut Self;
+    fn lockstep_checksum(&mut self, checksum: fn(&mut World) -> u64) -> &mut Self;
+}
+impl LockstepApp for App {
+    fn lockstep<I: NetworkInputData>(&mut self, schedule: impl ScheduleLabel) -> &mut Self {
+        let schedule = schedule.intern();
+        self.init_schedule(schedule)
+            .init_resource::<LockstepConfig>()
+            .init_resource::<NetworkInput<I>>()
+            .add_event::<LockstepDesync>()
+            .insert_resource(LockstepClient::<I> {
+                schedule,
+                local_player: 0,
+                next_step: None,
+                next_submit: 0,
+                steps: BTreeMap::new(),
+            })
+            .insert_resource(LockstepServer::<I> {
+                next_step: 0,
+                players: HashMap::new(),
+                pending: BTreeMap::new(),
+                checksums: BTreeMap::new(),
+            })
+            .add_systems(PreUpdate, receive_steps::<I>.run_if(has_client_config))
+            .add_systems(FixedUpdate, run_steps::<I>.run_if(has_client_config))
+            .add_systems(PreUpdate, relay_inputs::<I>.run_if(has_server_config))
+    }
+    fn lockstep_checksum(&mut self, checksum: fn(&mut World) -> u64) -> &mut Self {
+        self.insert_resource(LockstepChecksum(checksum))
+    }
+}
+#[derive(Serialize, Deserialize, Debug)]
+enum LockstepMessage<I> {
+    Start {
+        step: u32,
+        player: u32,
+    },
+    Input {
+        step: u32,
+        input: I,
+    },
+    Step {
+        step: u32,
+        inputs: Vec<(u32, I)>,
+    },
+    Checksum {
+        step: u32,
+        checksum: u64,
+    },
+    Desync {
+        step: u32,
+        checksums: Vec<(u32, u64)>,
+    },
+}
+impl<I> Identify for LockstepMessage<I> {
+    fn get_identifier(&self) -> u32 {
+        LOCKSTEP_IDENTIFIER
+    }
+}
+#[derive(Resource)]
+struct LockstepChecksum(fn(&mut World) -> u64);
+#[derive(Resource)]
+struct LockstepClient<I> {
+    schedule: InternedScheduleLabel,
+    local_player: u32,
+    next_step: Option<u32>,
+    next_submit: u32,
+    steps: BTreeMap<u32, Vec<(u32, I)>>,
+}
+#[derive(Resource)]
+struct LockstepServer<I> {
+    next_step: u32,
+    players: HashMap<u32, u32>,
+    pending: BTreeMap<u32, HashMap<u32, I>>,
+    checksums: BTreeMap<u32, Vec<(u32, u64)>>,
+}
+impl<I: NetworkInputData> LockstepServer<I> {
+    fn is_complete(&self, step: u32) -> bool {
+        self.players.iter().all(|(id, first_step)| {
+            step < *first_step
+                || self
+                    .pending
+                    .get(&step)
+                    .is_some_and(|inputs| inputs.contains_key(id))
+        })
+    }
+    fn is_too_far_ahead(&self, step: u32, input_delay: u32) -> bool {
+        step > self.next_step + input_delay + MAX_STEPS_AHEAD
+    }
+    fn receive_input(&mut self, id: u32, step: u32, input: I, input_delay: u32) {
+        if step < self.next_step || self.is_too_far_ahead(step, input_delay) {
+            return;
+        }
+        self.pending
+            .entry(step)
+            .or_default()
+            .entry(id)
+            .or_insert(input);
+    }
+    fn receive_checksum(
+        &mut self,
+        id: u32,
+        step: u32,
+        checksum: u64,
+        input_delay: u32,
+    ) -> Option<LockstepDesync> {
+        if self.is_too_far_ahead(step, input_delay) {
+            return None;
+        }
+        let checksums = self.checksums.entry(step).or_default();
+        checksums.push((id, checksum));
+        let desynced = checksums.iter().any(|(_, other)| *other != checksum);
+        let reported = self
+            .players
+            .iter()
+            .filter(|(_, first_step)| **first_step <= step)
+            .all(|(id, _)| checksums.iter().any(|(reporter, _)| reporter == id));
+        let oldest = self.next_step.saturating_sub(CHECKSUM_HISTORY);
+        self.checksums = self.checksums.split_off(&oldest);
+        if desynced {
+            let mut checksums = self.checksums.remove(&step)?;
+            checksums.sort();
+            return Some(LockstepDesync { step, checksums });
+        }
+        if reported {
+            self.checksums.remove(&step);
+        }
+        None
+    }
+}
+fn relay_inputs<I: NetworkInputData>(
+    mut server_data_reader: EventReader<ServerDataReadEvent>,
+    mut player_intergress_reader: EventReader<PlayerIntergressEvent>,
+    mut server_data_uploader: ResMut<ServerDataUploader>,
+    mut lockstep_server: ResMut<LockstepServer<I>>,
+    mut desync_writer: EventWriter<LockstepDesync>,
+    lockstep_config: Res<LockstepConfig>,
+) {
+    for event in player_intergress_reader.read() {
+        match event.intergress_type {
+            IntergressType::Joined => {
+                let step = lockstep_server.next_step;
+                lockstep_server
+                    .players
+                    .insert(event.id, step + lockstep_config.input_delay);
+                server_data_uploader.upload(
+                    LockstepMessage::<I>::Start {
+                        step,
+                        player: event.id,
+                    },
+                    Recipient::Single { id: event.id },
+                );
+            }
+            IntergressType::Left => {
+                lockstep_server.players.remove(&event.id);
+                for inputs in lockstep_server.pending.values_mut() {
+                    inputs.remove(&event.id);
+                }
+            }
+        }
+    }
+    for event in server_data_reader.read() {
+        if event.data_packet.identifier != LOCKSTEP_IDENTIFIER {
+            continue;
+        }
+        let message: LockstepMessage<I> = match bincode::deserialize(&event.data_packet.bytes) {
+            Ok(message) => message,
+            Err(err) => {
+                println!(
+                    "Failed to deserialize a lockstep message from id {}: {err:#?}",
+                    event.id
+                );
+                continue;
+            }
+        };
+        if !lockstep_server.players.contains_key(&event.id) {
+            continue;
+        }
+        match message {
+            LockstepMessage::Input { step, input } => {
+                lockstep_server.receive_input(event.id, step, input, lockstep_config.input_delay);
+            }
+            LockstepMessage::Checksum { step, checksum } => {
+                if let Some(desync) = lockstep_server.receive_checksum(
+                    event.id,
+                    step,
+                    checksum,
+                    lockstep_config.input_delay,
+                ) {
+                    server_data_uploader.upload(
+                        LockstepMessage::<I>::Desync {
+                            step: desync.step,
+                            checksums: desync.checksums.clone(),
+                        },
+                        Recipient::All,
+                    );
+                    desync_writer.send(desync);
+                }
+            }
+            _ => {}
+        }
+    }
+    while !lockstep_server.players.is_empty() {
+        let step = lockstep_server.next_step;
+        if !lockstep_server.is_complete(step) {
+            break;
+        }
+        let mut inputs: Vec<(u32, I)> = lockstep_server
+            .pending
+            .remove(&step)
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        inputs.sort_by_key(|(id, _)| *id);
+        server_data_uploader.upload(LockstepMessage::Step { step, inputs }, Recipient::All);
+        lockstep_server.next_step += 1;
+    }
+}
+fn receive_steps<I: NetworkInputData>(
+    mut client_data_reader: EventReader<ClientDataReadEvent>,
+    mut server_connection_change_reader: EventReader<ServerConnectionChangeEvent>,
+    mut lockstep_client: ResMut<LockstepClient<I>>,
+    mut desync_writer: EventWriter<LockstepDesync>,
+    lockstep_config: Res<LockstepConfig>,
+) {
+    for event in server_connection_change_reader.read() {
+        if let ConnectionChange::Disconnected = event.connection_change {
+            lockstep_client.next_step = None;
+            lockstep_client.steps.clear();
+        }
+    }
+    for event in client_data_reader.read() {
+        if event.data_packet.identifier != LOCKSTEP_IDENTIFIER {
+            continue;
+        }
+        let message: LockstepMessage<I> = match bincode::deserialize(&event.data_packet.bytes) {
+            Ok(message) => message,
+            Err(err) => {
+                println!("Failed to deserialize a lockstep message: {err:#?}");
+                continue;
+            }
+        };
+        match message {
+            LockstepMessage::Start { step, player } => {
+                lockstep_client.local_player = player;
+                lockstep_client.next_step = Some(step);
+                lockstep_client.next_submit = step + lockstep_config.input_delay;
+                lockstep_client.steps.clear();
+            }
+            LockstepMessage::Step { step, inputs }
+                if lockstep_client
+                    .next_step
+                    .is_some_and(|next_step| step >= next_step) =>
+            {
+                lockstep_client.steps.insert(step, inputs);
+            }
+            LockstepMessage::Desync { step, checksums } => {
+                desync_writer.send(LockstepDesync { step, checksums });
+            }
+            _ => {}
+        }
+    }
+}
+fn run_steps<I: NetworkInputData>(world: &mut World) {
+    let Some(next_step) = world.resource::<LockstepClient<I>>().next_step else {
+        return;
+    };
+    let input_delay = world.resource::<LockstepConfig>().input_delay;
+    let next_submit = world.resource::<LockstepClient<I>>().next_submit;
+    if next_submit <= next_step + input_delay {
+        let input = world.resource::<NetworkInput<I>>().0.clone();
+        if let Some(mut client_data_uploader) = world.get_resource_mut::<ClientDataUploader>() {
+            if client_data_uploader.is_connected() {
+                let _ = client_data_uploader.upload(LockstepMessage::Input {
+                    step: next_submit,
+                    input,
+                });
+                world.resource_mut::<LockstepClient<I>>().next_submit += 1;
+            }
+        }
+    }
+    let backlog = world.resource::<LockstepClient<I>>().steps.len();
+    let steps = if backlog as u32 > input_delay {
+        MAX_STEPS_PER_TICK
+    } else {
+        1
+    };
+    for _ in 0..steps {
+        if !run_step::<I>(world) {
+            break;
+        }
+    }
+}
+fn run_step<I: NetworkInputData>(world: &mut World) -> bool {
+    let mut lockstep_client = world.resource_mut::<LockstepClient<I>>();
+    let Some(step) = lockstep_client.next_step else {
+        return false;
+    };
+    let Some(inputs) = lockstep_client.steps.remove(&step) else {
+        return false;
+    };
+    lockstep_client.next_step = Some(step + 1);
+    let schedule = lockstep_client.schedule;
+    let local_player = lockstep_client.local_player;
+    world.insert_resource(LockstepInputs {
+        step,
+        local_player,
+        inputs,
+    });
+    world.run_schedule(schedule);
+    let checksum_interval = world.resource::<LockstepConfig>().checksum_interval.max(1);
+    if step % checksum_interval != 0 {
+        return true;
+    }
+    let Some(checksum) = world
+        .get_resource::<LockstepChecksum>()
+        .map(|checksum| checksum.0)
+    else {
+        return true;
+    };
+    let checksum = checksum(world);
+    if let Some(mut client_data_uploader) = world.get_resource_mut::<ClientDataUploader>() {
+        let _ = client_data_uploader.upload(LockstepMessage::<I>::Checksum { step, checksum });
+    }
+    true
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const INPUT_DELAY: u32 = 3;
+    fn server(players: &[(u32, u32)]) -> LockstepServer<u32> {
+        LockstepServer {
+            next_step: 10,
+            players: players.iter().copied().collect(),
+            pending: BTreeMap::new(),
+            checksums: BTreeMap::new(),
+        }
+    }
+    #[test]
+    fn completes_steps_once_every_player_sent_input() {
+        let mut server = server(&[(1, 0), (2, 12)]);
+        assert!(!server.is_complete(10));
+        server.receive_input(1, 10, 5, INPUT_DELAY);
+        assert!(server.is_complete(10));
+        server.receive_input(1, 12, 6, INPUT_DELAY);
+        assert!(!server.is_complete(12));
+        server.receive_input(2, 12, 7, INPUT_DELAY);
+        assert!(server.is_complete(12));
+        server.receive_input(1, 10, 8, INPUT_DELAY);
+        assert_eq!(server.pending[&10][&1], 5);
+    }
+    #[test]
+    fn drops_steps_outside_the_window() {
+        let mut server = server(&[(1, 0)]);
+        let last_step = 10 + INPUT_DELAY + MAX_STEPS_AHEAD;
+        server.receive_input(1, 9, 0, INPUT_DELAY);
+        server.receive_input(1, last_step + 1, 0, INPUT_DELAY);
+        server.receive_input(1, u32::MAX, 0, INPUT_DELAY);
+        assert!(server.pending.is_empty());
+        assert!(server
+            .receive_checksum(1, last_step + 1, 0, INPUT_DELAY)
+            .is_none());
+        assert!(server.checksums.is_empty());
+        server.receive_input(1, last_step, 0, INPUT_DELAY);
+        assert_eq!(server.pending.len(), 1);
+    }
+    #[test]
+    fn detects_desyncs() {
+        let mut server = server(&[(1, 0), (2, 0), (3, 20)]);
+        assert!(server.receive_checksum(2, 8, 7, INPUT_DELAY).is_none());
+        assert!(server.receive_checksum(1, 8, 7, INPUT_DELAY).is_none());
+        assert!(server.checksums.is_empty());
+        assert!(server.receive_checksum(2, 9, 4, INPUT_DELAY).is_none());
+        let desync = server.receive_checksum(1, 9, 5, INPUT_DELAY).unwrap();
+        assert_eq!(desync.step, 9);
+        assert_eq!(desync.checksums, vec![(1, 5), (2, 4)]);
+        assert!(server.checksums.is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{
+    entity_map::replicated_entity, has_client_config, has_server_config, ClientDataReadEvent,
+    ClientDataUploader, Identify, NetworkEntity, NetworkEntityMap, Recipient, RelevantEntities,
+    ServerDataReadEvent, ServerDataUploader, NETWORK_EVENT_IDENTIFIER,
+};
+use bevy::{ecs::event::ManualEventReader, prelude::*};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+type ReceiveFn = fn(&mut World, Option<u32>, Option<NetworkEntity>, &[u8]) -> bincode::Result<()>;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetworkDirection {
+    ClientToServer,
+    ServerToClient,
+    Bidirectional,
+}
+impl NetworkDirection {
+    fn sends_to_server(self) -> bool {
+        !matches!(self, NetworkDirection::ServerToClient)
+    }
+    fn sends_to_clients(self) -> bool {
+        !matches!(self, NetworkDirection::ClientToServer)
+    }
+}
+#[derive(Event, Clone, Debug)]
+pub struct ToServer<E> {
+    pub event: E,
+    pub target: Option<Entity>,
+}
+#[derive(Event)]
+pub struct ToClients<E> {
+    pub event: E,
+    pub recipient: Recipient,
+    pub target: Option<Entity>,
+}
+#[derive(Event, Clone, Debug)]
+pub struct FromClient<E> {
+    pub id: u32,
+    pub event: E,
+}
+#[derive(Serialize, Deserialize, Debug)]
+struct NetworkEventMessage {
+    index: u16,
+    target: Option<NetworkEntity>,
+    bytes: Vec<u8>,
+}
+impl Identify for NetworkEventMessage {
+    fn get_identifier(&self) -> u32 {
+        NETWORK_EVENT_IDENTIFIER
+    }
+}
+#[derive(Resource, Default)]
+struct NetworkEventRegistry {
+    events: Vec<ReceiveFn>,
+}
+#[derive(Resource)]
+struct NetworkEventIndex<E> {
+    index: u16,
+    direction: NetworkDirection,
+    marker: std::marker::PhantomData<E>,
+}
+pub trait NetworkEventApp {
+    fn add_network_event<E: Event + Clone + Serialize + DeserializeOwned>(
+        &mut self,
+        direction: NetworkDirection,
+    ) -> &mut Self;
+}
+impl NetworkEventApp for App {
+    fn add_network_event<E: Event + Clone + Serialize + DeserializeOwned>(
+        &mut self,
+        direction: NetworkDirection,
+    ) -> &mut Self {
+        if !self.world().contains_resource::<NetworkEventRegistry>() {
+            self.init_resource::<NetworkEventRegistry>().add_systems(
+                PreUpdate,
+                (
+                    receive_server_events.run_if(has_client_config),
+                    receive_client_events.run_if(has_server_config),
+                ),
+            );
+        }
+        let mut registry = self.world_mut().resource_mut::<NetworkEventRegistry>();
+        let index = registry.events.len() as u16;
+        registry.events.push(receive_event::<E>);
+        self.insert_resource(NetworkEventIndex::<E> {
+            index,
+            direction,
+            marker: std::marker::PhantomData,
+        })
+        .add_event::<E>()
+        .add_event::<ToServer<E>>()
+        .add_event::<ToClients<E>>()
+        .add_event::<FromClient<E>>()
+        .add_systems(
+            PostUpdate,
+            (
+                send_to_server::<E>.run_if(has_client_config),
+                send_to_clients::<E>.run_if(has_server_config),
+            ),
+        )
+    }
+}
+fn receive_event<E: Event + Clone + DeserializeOwned>(
+    world: &mut World,
+    sender: Option<u32>,
+    target: Option<NetworkEntity>,
+    bytes: &[u8],
+) -> bincode::Result<()> {
+    let direction = world.resource::<NetworkEventIndex<E>>().direction;
+    let allowed = match sender {
+        Some(_) => direction.sends_to_server(),
+        None => direction.sends_to_clients(),
+    };
+    if !allowed {
+        return Ok(());
+    }
+    let network_entity_map = world.resource::<NetworkEntityMap>().clone();
+    let event: E = network_entity_map.deserialize(bytes)?;
+    let target = match (sender, target) {
+        (Some(id), Some(target)) => {
+            let Some(target) = replicated_entity(world, target).filter(|target| {
+                world
+                    .resource::<RelevantEntities>()
+                    .is_relevant(id, *target)
+            }) else {
+                return Ok(());
+            };
+            Some(target)
+        }
+        (_, target) => target
+            .and_then(|target| network_entity_map.to_local(target))
+            .filter(|target| world.get_entity(*target).is_some()),
+    };
+    match sender {
+        Some(id) => deliver_event(world, FromClient { id, event }, target),
+        None => deliver_event(world, event, target),
+    }
+    Ok(())
+}
+fn deliver_event<E: Event + Clone>(world: &mut World, event: E, target: Option<Entity>) {
+    world.send_event(event.clone());
+    match target {
+        Some(target) => world.trigger_targets(event, target),
+        None => world.trigger(event),
+    }
+}
+fn send_to_server<E: Event + Serialize>(
+    mut to_server: ResMut<Events<ToServer<E>>>,
+    network_event_index: Res<NetworkEventIndex<E>>,
+    mut client_data_uploader: ResMut<ClientDataUploader>,
+) {
+    if !network_event_index.direction.sends_to_server() {
+        to_server.clear();
+        return;
+    }
+    let network_entity_map = client_data_uploader.network_entity_map.clone();
+    for to_server in to_server.drain() {
+        let bytes = match network_entity_map.scope(|| bincode::serialize(&to_server.event)) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                println!("Failed to serialize a network event: {err:#?}");
+                continue;
+            }
+        };
+        let _ = client_data_uploader.upload(NetworkEventMessage {
+            index: network_event_index.index,
+            target: to_server
+                .target
+                .and_then(|target| network_entity_map.to_network(target)),
+            bytes,
+        });
+    }
+}
+fn send_to_clients<E: Event + Serialize>(
+    mut to_clients: ResMut<Events<ToClients<E>>>,
+    network_event_index: Res<NetworkEventIndex<E>>,
+    mut server_data_uploader: ResMut<ServerDataUploader>,
+) {
+    if !network_event_index.direction.sends_to_clients() {
+        to_clients.clear();
+        return;
+    }
+    for to_clients in to_clients.drain() {
+        let bytes = match bincode::serialize(&to_clients.event) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                println!("Failed to serialize a network event: {err:#?}");
+                continue;
+            }
+        };
+        server_data_uploader.upload(
+            NetworkEventMessage {
+                index: network_event_index.index,
+                target: to_clients.target.map(NetworkEntity::from_server_entity),
+                bytes,
+            },
+            to_clients.recipient,
+        );
+    }
+}
+fn receive_server_events(
+    world: &mut World,
+    mut client_data_read_reader: Local<ManualEventReader<ClientDataReadEvent>>,
+) {
+    let messages: Vec<NetworkEventMessage> = client_data_read_reader
+        .read(world.resource::<Events<ClientDataReadEvent>>())
+        .filter(|event| event.data_packet.identifier == NETWORK_EVENT_IDENTIFIER)
+        .filter_map(|event| bincode::deserialize(&event.data_packet.bytes).ok())
+        .collect();
+    world.resource_scope(|world, registry: Mut<NetworkEventRegistry>| {
+        for message in messages {
+            let Some(receive) = registry.events.get(message.index as usize) else {
+                continue;
+            };
+            if let Err(err) = receive(world, None, message.target, &message.bytes) {
+                println!("Failed to deserialize a network event: {err:#?}");
+            }
+        }
+    });
+}
+fn receive_client_events(
+    world: &mut World,
+    mut server_data_read_reader: Local<ManualEventReader<ServerDataReadEvent>>,
+) {
+    let messages: Vec<(u32, NetworkEventMessage)> = server_data_read_reader
+        .read(world.resource::<Events<ServerDataReadEvent>>())
+        .filter(|event| event.data_packet.identifier == NETWORK_EVENT_IDENTIFIER)
+        .filter_map(
+            |event| match bincode::deserialize(&event.data_packet.bytes) {
+                Ok(message) => Some((event.id, message)),
+                Err(err) => {
+                    println!(
+                        "Failed to deserialize a network event from id {}: {err:#?}",
+                        event.id
+                    );
+                    None
+                }
+            },
+        )
+        .collect();
+    world.resource_scope(|world, registry: Mut<NetworkEventRegistry>| {
+        for (id, message) in messages {
+            let Some(receive) = registry.events.get(message.index as usize) else {
+                continue;
+            };
+            if let Err(err) = receive(world, Some(id), message.target, &message.bytes) {
+                println!("Failed to deserialize a network event from id {id}: {err:#?}");
+            }
+        }
+    });
+}
@@ -0,0 +1,156 @@
+use crate::{
+    has_client_config, has_server_config, input::SentInputs, replication::ReplicationSet,
+    ClientDataUploader, ConnectionChange, NetworkInputApp, NetworkInputData, NetworkInputs,
+    ReplicationApp, ServerConnectionChangeEvent,
+};
+use bevy::{
+    ecs::schedule::{InternedScheduleLabel, ScheduleLabel},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+const MAX_PREDICTED_INPUTS: usize = 256;
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Predicted;
+#[derive(Component, Clone, Copy, Debug)]
+pub struct InputOwner(pub u32);
+#[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProcessedInputTick(pub u32);
+#[derive(Component, Clone, Debug)]
+pub struct PredictedInput<I>(pub I);
+pub trait PredictionApp {
+    fn predict<I: NetworkInputData>(&mut self, schedule: impl ScheduleLabel) -> &mut Self;
+}
+impl PredictionApp for App {
+    fn predict<I: NetworkInputData>(&mut self, schedule: impl ScheduleLabel) -> &mut Self {
+        let schedule = schedule.intern();
+        self.init_schedule(schedule)
+            .replicate::<ProcessedInputTick>()
+            .add_network_input::<I>()
+            .insert_resource(Prediction::<I> {
+                schedule,
+                history: VecDeque::new(),
+                reconcile_from: None,
+            })
+            .add_systems(
+                PreUpdate,
+                (
+                    detect_corrections::<I>,
+                    reconcile::<I>,
+                    reset_prediction::<I>,
+                )
+                    .chain()
+                    .after(ReplicationSet::Receive)
+                    .run_if(has_client_config),
+            )
+            .add_systems(FixedUpdate, predict::<I>.run_if(has_client_config))
+            .add_systems(FixedUpdate, simulate_server::<I>.run_if(has_server_config))
+    }
+}
+#[derive(Resource)]
+struct Prediction<I> {
+    schedule: InternedScheduleLabel,
+    history: VecDeque<(u32, I)>,
+    reconcile_from: Option<u32>,
+}
+fn predict<I: NetworkInputData>(world: &mut World) {
+    if !world
+        .get_resource::<ClientDataUploader>()
+        .is_some_and(|client_data_uploader| client_data_uploader.is_connected())
+    {
+        return;
+    }
+    let Some((tick, input)) = world
+        .resource::<SentInputs<I>>()
+        .latest()
+        .map(|(tick, input)| (tick, input.clone()))
+    else {
+        return;
+    };
+    let mut prediction = world.resource_mut::<Prediction<I>>();
+    let schedule = prediction.schedule;
+    prediction.history.push_back((tick, input.clone()));
+    while prediction.history.len() > MAX_PREDICTED_INPUTS {
+        prediction.history.pop_front();
+    }
+    simulate_predicted(world, input, schedule);
+}
+fn simulate_predicted<I: NetworkInputData>(
+    world: &mut World,
+    input: I,
+    schedule: InternedScheduleLabel,
+) {
+    let predicted: Vec<Entity> = world
+        .query_filtered::<Entity, With<Predicted>>()
+        .iter(world)
+        .collect();
+    for entity in predicted {
+        world
+            .entity_mut(entity)
+            .insert(PredictedInput(input.clone()));
+    }
+    world.run_schedule(schedule);
+}
+fn detect_corrections<I: NetworkInputData>(
+    corrected: Query<&ProcessedInputTick, (With<Predicted>, Changed<ProcessedInputTick>)>,
+    mut prediction: ResMut<Prediction<I>>,
+) {
+    if let Some(tick) = corrected.iter().map(|tick| tick.0).min() {
+        prediction.reconcile_from = Some(tick);
+    }
+}
+fn reconcile<I: NetworkInputData>(world: &mut World) {
+    let mut prediction = world.resource_mut::<Prediction<I>>();
+    let Some(acked) = prediction.reconcile_from.take() else {
+        return;
+    };
+    prediction.history.retain(|(tick, _)| *tick > acked);
+    let schedule = prediction.schedule;
+    let inputs: Vec<I> = prediction
+        .history
+        .iter()
+        .map(|(_, input)| input.clone())
+        .collect();
+    let time = *world.resource::<Time>();
+    *world.resource_mut::<Time>() = world.resource::<Time<Fixed>>().as_generic();
+    for input in inputs {
+        simulate_predicted(world, input, schedule);
+    }
+    *world.resource_mut::<Time>() = time;
+}
+fn reset_prediction<I: NetworkInputData>(
+    mut server_connection_change_reader: EventReader<ServerConnectionChangeEvent>,
+    mut prediction: ResMut<Prediction<I>>,
+) {
+    for event in server_connection_change_reader.read() {
+        if let ConnectionChange::Disconnected = event.connection_change {
+            prediction.history.clear();
+            prediction.reconcile_from = None;
+        }
+    }
+}
+fn simulate_server<I: NetworkInputData>(world: &mut World) {
+    let owned: Vec<(Entity, u32)> = world
+        .query::<(Entity, &InputOwner)>()
+        .iter(world)
+        .map(|(entity, owner)| (entity, owner.0))
+        .collect();
+    world.resource_scope(|world, network_inputs: Mut<NetworkInputs<I>>| {
+        for (entity, owner) in owned {
+            let mut entity = world.entity_mut(entity);
+            match network_inputs.current(owner) {
+                Some(current) => {
+                    entity.insert(PredictedInput(current.input.clone()));
+                    if !current.missing {
+                        entity.insert(ProcessedInputTick(current.tick));
+                    }
+                }
+                None => {
+                    entity.remove::<PredictedInput<I>>();
+                }
+            }
+        }
+    });
+    let schedule = world.resource::<Prediction<I>>().schedule;
+    world.run_schedule(schedule);
+}
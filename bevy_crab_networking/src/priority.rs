@@ -0,0 +1,148 @@
+use crate::{
+    has_server_config, replication::ReplicationSet, snapshot::Snapshot, NetworkEntity,
+    RelevantEntities, Replicated, ReplicationConfig,
+};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use std::{cmp::Ordering, sync::Arc};
+const ENTITY_OVERHEAD: usize = 12;
+const COMPONENT_OVERHEAD: usize = 6;
+type PriorityQueryData<'a> = (Entity, Option<&'a NetworkPriority>, Option<&'a Transform>);
+#[derive(Component, Clone, Copy, Debug)]
+pub struct NetworkPriority(pub f32);
+pub struct PriorityContext<'a> {
+    pub id: u32,
+    pub entity: Entity,
+    pub position: Option<Vec3>,
+    pub viewers: &'a [Vec3],
+}
+#[derive(Resource)]
+pub(crate) struct PriorityFn(pub(crate) fn(&PriorityContext) -> f32);
+#[derive(Resource, Default)]
+pub(crate) struct ReplicationPriorities {
+    clients: HashMap<u32, ClientPriorities>,
+}
+#[derive(Default)]
+struct ClientPriorities {
+    gains: HashMap<NetworkEntity, f32>,
+    accumulated: HashMap<NetworkEntity, f32>,
+}
+impl ReplicationPriorities {
+    pub(crate) fn limit(
+        &mut self,
+        id: u32,
+        view: &Arc<Snapshot>,
+        sent: Option<&Arc<Snapshot>>,
+        budget: usize,
+    ) -> (Arc<Snapshot>, bool) {
+        let empty = Snapshot::default();
+        let sent = sent.map_or(&empty, |sent| sent.as_ref());
+        let client = self.clients.entry(id).or_default();
+        client
+            .accumulated
+            .retain(|network_entity, _| view.entities.contains_key(network_entity));
+        let mut pending: Vec<(NetworkEntity, f32, usize)> = view
+            .entities
+            .iter()
+            .filter(|(network_entity, components)| {
+                sent.entities.get(*network_entity) != Some(*components)
+            })
+            .map(|(network_entity, components)| {
+                let sent_components = sent.entities.get(network_entity);
+                let size = components
+                    .iter()
+                    .filter(|(component_index, bytes)| {
+                        sent_components.and_then(|sent| sent.get(*component_index)) != Some(*bytes)
+                    })
+                    .map(|(_, bytes)| bytes.len() + COMPONENT_OVERHEAD)
+                    .sum::<usize>()
+                    + ENTITY_OVERHEAD;
+                let gain = client.gains.get(network_entity).copied().unwrap_or(1.);
+                let priority = client.accumulated.entry(*network_entity).or_default();
+                *priority += gain;
+                (*network_entity, *priority, size)
+            })
+            .collect();
+        pending.sort_by(|(_, a, _), (_, b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+        let mut used = 0;
+        let mut selected = HashSet::new();
+        for (network_entity, _, size) in pending.iter() {
+            if used + size > budget && !selected.is_empty() {
+                continue;
+            }
+            used += size;
+            selected.insert(*network_entity);
+        }
+        for network_entity in selected.iter() {
+            client.accumulated.remove(network_entity);
+        }
+        if selected.len() == pending.len() {
+            return (view.clone(), true);
+        }
+        let limited = Snapshot {
+            entities: view
+                .entities
+                .iter()
+                .filter_map(|(network_entity, components)| {
+                    let deferred = !selected.contains(network_entity)
+                        && sent.entities.get(network_entity) != Some(components);
+                    match deferred {
+                        true => sent
+                            .entities
+                            .get(network_entity)
+                            .map(|sent| (*network_entity, sent.clone())),
+                        false => Some((*network_entity, components.clone())),
+                    }
+                })
+                .collect(),
+        };
+        (Arc::new(limited), false)
+    }
+}
+pub(crate) struct PriorityPlugin;
+impl Plugin for PriorityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplicationPriorities>().add_systems(
+            PostUpdate,
+            update_priorities
+                .in_set(ReplicationSet::Entities)
+                .run_if(has_server_config),
+        );
+    }
+}
+fn update_priorities(
+    mut replication_priorities: ResMut<ReplicationPriorities>,
+    relevant_entities: Res<RelevantEntities>,
+    replication_config: Res<ReplicationConfig>,
+    priority_fn: Option<Res<PriorityFn>>,
+    replicated: Query<PriorityQueryData, With<Replicated>>,
+) {
+    if replication_config.bandwidth_budget.is_none() {
+        replication_priorities.clients.clear();
+        return;
+    }
+    let priority_fn = priority_fn.map(|priority_fn| priority_fn.0);
+    replication_priorities
+        .clients
+        .retain(|id, _| relevant_entities.has_client(*id));
+    for (id, viewers) in relevant_entities.viewers() {
+        let client = replication_priorities.clients.entry(id).or_default();
+        client.gains = replicated
+            .iter()
+            .map(|(entity, priority, transform)| {
+                let mut gain = priority.map_or(1., |priority| priority.0);
+                if let Some(priority_fn) = priority_fn {
+                    gain *= priority_fn(&PriorityContext {
+                        id,
+                        entity,
+                        position: transform.map(|transform| transform.translation),
+                        viewers,
+                    });
+                }
+                (NetworkEntity::from_server_entity(entity), gain.max(0.))
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{
+    has_server_config, replication::ReplicationSet, snapshot::Snapshot, InRoom, IntergressType,
+    NetworkEntity, NetworkRooms, PlayerIntergressEvent,
+};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use std::sync::Arc;
+type RuledQueryData<'a> = (
+    Entity,
+    Option<&'a NetworkRelevance>,
+    Option<&'a InRoom>,
+    Option<&'a Transform>,
+);
+type RuledQueryFilter = Or<(With<NetworkRelevance>, With<InRoom>)>;
+#[derive(Component, Clone, Debug)]
+pub enum NetworkRelevance {
+    Always,
+    Never,
+    Radius(f32),
+    Grid,
+    Team(u32),
+    Clients(HashSet<u32>),
+    All(Vec<NetworkRelevance>),
+    Any(Vec<NetworkRelevance>),
+}
+#[derive(Component, Clone, Copy, Debug)]
+pub struct NetworkViewer {
+    pub id: u32,
+}
+#[derive(Resource)]
+pub struct RelevanceConfig {
+    pub cell_size: f32,
+}
+impl Default for RelevanceConfig {
+    fn default() -> Self {
+        RelevanceConfig { cell_size: 50. }
+    }
+}
+#[derive(Resource, Default)]
+pub struct NetworkTeams {
+    teams: HashMap<u32, u32>,
+}
+impl NetworkTeams {
+    pub fn set(&mut self, id: u32, team: u32) {
+        self.teams.insert(id, team);
+    }
+    pub fn remove(&mut self, id: u32) {
+        self.teams.remove(&id);
+    }
+    pub fn get(&self, id: u32) -> Option<u32> {
+        self.teams.get(&id).copied()
+    }
+}
+#[derive(Resource, Default)]
+pub struct RelevantEntities {
+    clients: HashMap<u32, HashSet<Entity>>,
+    ruled: HashSet<Entity>,
+    viewers: HashMap<u32, Vec<Vec3>>,
+    pub(crate) changed: bool,
+}
+impl RelevantEntities {
+    pub fn is_relevant(&self, id: u32, entity: Entity) -> bool {
+        !self.ruled.contains(&entity)
+            || self
+                .clients
+                .get(&id)
+                .is_some_and(|relevant| relevant.contains(&entity))
+    }
+    pub(crate) fn has_client(&self, id: u32) -> bool {
+        self.viewers.contains_key(&id)
+    }
+    pub(crate) fn viewers(&self) -> impl Iterator<Item = (u32, &[Vec3])> {
+        self.viewers
+            .iter()
+            .map(|(id, positions)| (*id, positions.as_slice()))
+    }
+    fn is_network_entity_relevant(&self, id: u32, network_entity: NetworkEntity) -> bool {
+        Entity::try_from_bits(network_entity.0).map_or(true, |entity| self.is_relevant(id, entity))
+    }
+    pub(crate) fn filter(&self, id: u32, snapshot: &Arc<Snapshot>) -> Arc<Snapshot> {
+        let hidden = snapshot
+            .entities
+            .keys()
+            .any(|network_entity| !self.is_network_entity_relevant(id, *network_entity));
+        if !hidden {
+            return snapshot.clone();
+        }
+        Arc::new(Snapshot {
+            entities: snapshot
+                .entities
+                .iter()
+                .filter(|(network_entity, _)| self.is_network_entity_relevant(id, **network_entity))
+                .map(|(network_entity, components)| (*network_entity, components.clone()))
+                .collect(),
+        })
+    }
+}
+struct ClientView {
+    id: u32,
+    team: Option<u32>,
+    positions: Vec<Vec3>,
+    cells: HashSet<IVec3>,
+}
+impl NetworkRelevance {
+    fn evaluate(&self, view: &ClientView, position: Option<Vec3>, cell_size: f32) -> bool {
+        match self {
+            NetworkRelevance::Always => true,
+            NetworkRelevance::Never => false,
+            NetworkRelevance::Radius(radius) => position.is_some_and(|position| {
+                view.positions
+                    .iter()
+                    .any(|viewer| viewer.distance_squared(position) <= radius * radius)
+            }),
+            NetworkRelevance::Grid => {
+                position.is_some_and(|position| view.cells.contains(&cell(position, cell_size)))
+            }
+            NetworkRelevance::Team(team) => view.team == Some(*team),
+            NetworkRelevance::Clients(ids) => ids.contains(&view.id),
+            NetworkRelevance::All(rules) => rules
+                .iter()
+                .all(|rule| rule.evaluate(view, position, cell_size)),
+            NetworkRelevance::Any(rules) => rules
+                .iter()
+                .any(|rule| rule.evaluate(view, position, cell_size)),
+        }
+    }
+}
+fn cell(position: Vec3, cell_size: f32) -> IVec3 {
+    (position / cell_size.max(f32::EPSILON)).floor().as_ivec3()
+}
+pub(crate) struct RelevancePlugin;
+impl Plugin for RelevancePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RelevanceConfig>()
+            .init_resource::<NetworkTeams>()
+            .init_resource::<RelevantEntities>()
+            .add_systems(
+                PostUpdate,
+                update_relevance
+                    .in_set(ReplicationSet::Relevance)
+                    .run_if(has_server_config),
+            );
+    }
+}
+#[allow(clippy::too_many_arguments)]
+fn update_relevance(
+    mut player_intergress_reader: EventReader<PlayerIntergressEvent>,
+    mut clients: Local<HashSet<u32>>,
+    mut relevant_entities: ResMut<RelevantEntities>,
+    mut network_teams: ResMut<NetworkTeams>,
+    viewers: Query<(&NetworkViewer, &Transform)>,
+    ruled: Query<RuledQueryData, RuledQueryFilter>,
+    network_rooms: Res<NetworkRooms>,
+    relevance_config: Res<RelevanceConfig>,
+) {
+    for event in player_intergress_reader.read() {
+        match event.intergress_type {
+            IntergressType::Joined => {
+                clients.insert(event.id);
+            }
+            IntergressType::Left => {
+                clients.remove(&event.id);
+                network_teams.remove(event.id);
+            }
+        }
+    }
+    let cell_size = relevance_config.cell_size;
+    let mut views: HashMap<u32, ClientView> = clients
+        .iter()
+        .map(|id| {
+            (
+                *id,
+                ClientView {
+                    id: *id,
+                    team: network_teams.get(*id),
+                    positions: vec![],
+                    cells: HashSet::new(),
+                },
+            )
+        })
+        .collect();
+    for (viewer, transform) in viewers.iter() {
+        let Some(view) = views.get_mut(&viewer.id) else {
+            continue;
+        };
+        let center = cell(transform.translation, cell_size);
+        view.positions.push(transform.translation);
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    view.cells.insert(center + IVec3::new(x, y, z));
+                }
+            }
+        }
+    }
+    let ruled_entities: HashSet<Entity> = ruled.iter().map(|(entity, ..)| entity).collect();
+    let relevant: HashMap<u32, HashSet<Entity>> = views
+        .values()
+        .map(|view| {
+            let relevant = ruled
+                .iter()
+                .filter(|(_, rule, in_room, transform)| {
+                    in_room.is_none_or(|in_room| network_rooms.contains(&in_room.0, view.id))
+                        && rule.is_none_or(|rule| {
+                            rule.evaluate(
+                                view,
+                                transform.map(|transform| transform.translation),
+                                cell_size,
+                            )
+                        })
+                })
+                .map(|(entity, ..)| entity)
+                .collect();
+            (view.id, relevant)
+        })
+        .collect();
+    let changed =
+        relevant_entities.ruled != ruled_entities || relevant_entities.clients != relevant;
+    if changed {
+        relevant_entities.ruled = ruled_entities;
+        relevant_entities.clients = relevant;
+    }
+    relevant_entities.changed = changed;
+    relevant_entities.viewers = views
+        .into_iter()
+        .map(|(id, view)| (id, view.positions))
+        .collect();
+}
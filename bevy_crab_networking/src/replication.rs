@@ -0,0 +1,603 @@
+use crate::{
+    authority::{is_locally_owned, ClientAuthority},
+    entity_map::{track_replicated, untrack_replicated},
+    has_client_config, has_server_config,
+    interpolation::{Interpolation, InterpolationSet, ServerClock},
+    priority::{PriorityContext, PriorityFn, ReplicationPriorities},
+    resource,
+    snapshot::{Snapshot, SnapshotAck, SnapshotHistory, SnapshotMessage, SnapshotTime},
+    tick::TickSet,
+    ClientDataReadEvent, ClientDataUploader, ConnectionChange, DataPacket, IntergressType,
+    NetworkEntity, NetworkEntityMap, PlayerIntergressEvent, Predicted, Recipient, RelevantEntities,
+    ServerConnectionChangeEvent, ServerDataReadEvent, ServerDataUploader,
+    REPLICATION_ACK_IDENTIFIER, REPLICATION_IDENTIFIER,
+};
+use bevy::{
+    ecs::event::ManualEventReader,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
+type ReplicatedComponents<'w, 's, 'a, C> = Query<'w, 's, (Entity, Ref<'a, C>), With<Replicated>>;
+const PARENT_COMPONENT: u16 = u16::MAX;
+pub(crate) const AUTHORITY_COMPONENT: u16 = u16::MAX - 1;
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Replicated;
+#[derive(Resource)]
+pub struct ReplicationConfig {
+    pub history_size: usize,
+    pub bandwidth_budget: Option<usize>,
+}
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        ReplicationConfig {
+            history_size: 32,
+            bandwidth_budget: None,
+        }
+    }
+}
+pub trait ReplicationApp {
+    fn replicate<C: Component + Serialize + DeserializeOwned>(&mut self) -> &mut Self;
+    fn replication_priority(&mut self, priority: fn(&PriorityContext) -> f32) -> &mut Self;
+    fn replicate_resource<R: Resource + Serialize + DeserializeOwned>(&mut self) -> &mut Self;
+    fn replicate_resource_filtered<R: Resource + Serialize + DeserializeOwned>(
+        &mut self,
+        filter: fn(u32, &R) -> bool,
+    ) -> &mut Self;
+}
+impl ReplicationApp for App {
+    fn replicate<C: Component + Serialize + DeserializeOwned>(&mut self) -> &mut Self {
+        self.init_resource::<ReplicationRegistry>();
+        let component_index = self
+            .world()
+            .resource::<ReplicationRegistry>()
+            .components
+            .len() as u16;
+        replicate_component::<C>(self, component_index)
+    }
+    fn replication_priority(&mut self, priority: fn(&PriorityContext) -> f32) -> &mut Self {
+        self.insert_resource(PriorityFn(priority))
+    }
+    fn replicate_resource<R: Resource + Serialize + DeserializeOwned>(&mut self) -> &mut Self {
+        resource::replicate_resource::<R>(self, None);
+        self
+    }
+    fn replicate_resource_filtered<R: Resource + Serialize + DeserializeOwned>(
+        &mut self,
+        filter: fn(u32, &R) -> bool,
+    ) -> &mut Self {
+        resource::replicate_resource::<R>(self, Some(filter));
+        self
+    }
+}
+pub(crate) fn replicate_component<C: Component + Serialize + DeserializeOwned>(
+    app: &mut App,
+    component_index: u16,
+) -> &mut App {
+    app.init_resource::<ReplicationRegistry>();
+    let mut registry = app.world_mut().resource_mut::<ReplicationRegistry>();
+    let component_fns = ComponentFns {
+        insert: insert_component::<C>,
+        remove: remove_component::<C>,
+    };
+    match component_index as usize == registry.components.len() {
+        true => registry.components.push(component_fns),
+        false => {
+            registry.reserved.insert(component_index, component_fns);
+        }
+    }
+    app.add_systems(
+        PostUpdate,
+        collect_component::<C>(component_index)
+            .in_set(ReplicationSet::Components)
+            .run_if(has_server_config),
+    )
+}
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum ReplicationSet {
+    Receive,
+    Relevance,
+    Entities,
+    Components,
+    Send,
+}
+#[derive(Resource, Default)]
+pub(crate) struct ReplicationRegistry {
+    components: Vec<ComponentFns>,
+    reserved: HashMap<u16, ComponentFns>,
+}
+impl ReplicationRegistry {
+    fn get(&self, component_index: u16) -> Option<&ComponentFns> {
+        self.reserved
+            .get(&component_index)
+            .or_else(|| self.components.get(component_index as usize))
+    }
+}
+struct ComponentFns {
+    insert: fn(&mut EntityWorldMut, &[u8], SnapshotTime) -> bincode::Result<()>,
+    remove: fn(&mut EntityWorldMut),
+}
+#[derive(Resource)]
+struct ServerSnapshots {
+    history: SnapshotHistory,
+    current: Snapshot,
+    latest_time: SnapshotTime,
+    last_frame_time: f64,
+    clients: HashMap<u32, ClientBaseline>,
+}
+struct ClientBaseline {
+    acked: Option<u32>,
+    sent: Option<u32>,
+    pending: bool,
+    views: SnapshotHistory,
+}
+#[derive(Resource)]
+struct ClientSnapshots {
+    history: SnapshotHistory,
+    applied: Arc<Snapshot>,
+}
+pub(crate) struct ReplicationPlugin;
+impl Plugin for ReplicationPlugin {
+    fn build(&self, app: &mut App) {
+        app.world_mut()
+            .register_component_hooks::<Replicated>()
+            .on_add(track_replicated)
+            .on_remove(untrack_replicated);
+        app.init_resource::<ReplicationRegistry>()
+            .init_resource::<ReplicationConfig>()
+            .configure_sets(FixedPostUpdate, ReplicationSet::Send.before(TickSet::Flush))
+            .configure_sets(
+                PostUpdate,
+                (
+                    ReplicationSet::Relevance,
+                    ReplicationSet::Entities,
+                    ReplicationSet::Components,
+                )
+                    .chain(),
+            )
+            .add_systems(Startup, replication_setup)
+            .add_systems(
+                PreUpdate,
+                (apply_snapshots, despawn_on_disconnect)
+                    .chain()
+                    .in_set(ReplicationSet::Receive)
+                    .before(InterpolationSet)
+                    .run_if(has_client_config),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    (track_clients, collect_entities)
+                        .chain()
+                        .in_set(ReplicationSet::Entities),
+                    collect_parents.in_set(ReplicationSet::Components),
+                    push_snapshot.after(ReplicationSet::Components),
+                )
+                    .run_if(has_server_config),
+            )
+            .add_systems(
+                FixedPostUpdate,
+                send_snapshots
+                    .in_set(ReplicationSet::Send)
+                    .run_if(has_server_config),
+            );
+    }
+}
+fn replication_setup(mut commands: Commands, replication_config: Res<ReplicationConfig>) {
+    commands.insert_resource(ServerSnapshots {
+        history: SnapshotHistory::new(replication_config.history_size),
+        current: Snapshot::default(),
+        latest_time: SnapshotTime::default(),
+        last_frame_time: 0.,
+        clients: HashMap::new(),
+    });
+    commands.insert_resource(ClientSnapshots {
+        history: SnapshotHistory::new(replication_config.history_size),
+        applied: Arc::new(Snapshot::default()),
+    });
+}
+fn insert_component<C: Component + DeserializeOwned>(
+    entity: &mut EntityWorldMut,
+    bytes: &[u8],
+    time: SnapshotTime,
+) -> bincode::Result<()> {
+    let component: C = bincode::deserialize(bytes)?;
+    if entity.world().contains_resource::<ClientAuthority<C>>() && is_locally_owned(entity) {
+        return Ok(());
+    }
+    let receive = entity
+        .world()
+        .get_resource::<Interpolation<C>>()
+        .map(|interpolation| interpolation.receive)
+        .filter(|_| !entity.contains::<Predicted>());
+    match receive {
+        Some(receive) => receive(entity, component, time),
+        None => {
+            entity.insert(component);
+        }
+    }
+    Ok(())
+}
+fn remove_component<C: Component>(entity: &mut EntityWorldMut) {
+    entity.remove::<C>();
+}
+fn track_clients(
+    mut player_intergress_reader: EventReader<PlayerIntergressEvent>,
+    mut server_data_reader: EventReader<ServerDataReadEvent>,
+    mut server_snapshots: ResMut<ServerSnapshots>,
+    replication_config: Res<ReplicationConfig>,
+) {
+    for event in player_intergress_reader.read() {
+        match event.intergress_type {
+            IntergressType::Joined => {
+                server_snapshots.clients.insert(
+                    event.id,
+                    ClientBaseline {
+                        acked: None,
+                        sent: None,
+                        pending: false,
+                        views: SnapshotHistory::new(replication_config.history_size),
+                    },
+                );
+            }
+            IntergressType::Left => {
+                server_snapshots.clients.remove(&event.id);
+            }
+        }
+    }
+    for event in server_data_reader.read() {
+        if event.data_packet.identifier != REPLICATION_ACK_IDENTIFIER {
+            continue;
+        }
+        let Ok(SnapshotAck(acked)) = bincode::deserialize(&event.data_packet.bytes) else {
+            println!(
+                "Received an invalid snapshot acknowledgement from id: {}",
+                event.id
+            );
+            continue;
+        };
+        if let Some(client) = server_snapshots.clients.get_mut(&event.id) {
+            if acked.is_none() {
+                client.sent = None;
+                client.views.clear();
+            }
+            client.acked = acked;
+        }
+    }
+}
+fn collect_entities(
+    replicated: Query<Entity, With<Replicated>>,
+    mut server_snapshots: ResMut<ServerSnapshots>,
+) {
+    server_snapshots.current = Snapshot {
+        entities: replicated
+            .iter()
+            .map(|entity| (NetworkEntity::from_server_entity(entity), default()))
+            .collect(),
+    };
+}
+fn collect_parents(
+    parents: Query<(Entity, &Parent), With<Replicated>>,
+    replicated: Query<(), With<Replicated>>,
+    mut server_snapshots: ResMut<ServerSnapshots>,
+) {
+    for (entity, parent) in parents.iter() {
+        if !replicated.contains(parent.get()) {
+            continue;
+        }
+        let Some(components) = server_snapshots
+            .current
+            .entities
+            .get_mut(&NetworkEntity::from_server_entity(entity))
+        else {
+            continue;
+        };
+        let parent = NetworkEntity::from_server_entity(parent.get());
+        components.insert(
+            PARENT_COMPONENT,
+            bincode::serialize(&parent).unwrap().into(),
+        );
+    }
+}
+fn collect_component<C: Component + Serialize>(
+    component_index: u16,
+) -> impl FnMut(ReplicatedComponents<C>, ResMut<ServerSnapshots>) {
+    move |components, mut server_snapshots| {
+        let server_snapshots = server_snapshots.as_mut();
+        let latest = server_snapshots.history.latest().map(|(_, latest)| latest);
+        for (entity, component) in components.iter() {
+            let network_entity = NetworkEntity::from_server_entity(entity);
+            let previous = latest
+                .and_then(|latest| latest.entities.get(&network_entity))
+                .and_then(|components| components.get(&component_index));
+            let bytes = match previous {
+                Some(previous) if !component.is_changed() => previous.clone(),
+                _ => match bincode::serialize(component.as_ref()) {
+                    Ok(bytes) => Bytes::from(bytes),
+                    Err(err) => {
+                        println!("Failed to serialize a replicated component: {err:#?}");
+                        continue;
+                    }
+                },
+            };
+            if let Some(components) = server_snapshots.current.entities.get_mut(&network_entity) {
+                components.insert(component_index, bytes);
+            }
+        }
+    }
+}
+fn push_snapshot(
+    mut server_snapshots: ResMut<ServerSnapshots>,
+    relevant_entities: Res<RelevantEntities>,
+    time: Res<Time<Real>>,
+) {
+    let server_snapshots = server_snapshots.as_mut();
+    let now = time.elapsed_seconds_f64();
+    let last_frame_time = std::mem::replace(&mut server_snapshots.last_frame_time, now);
+    let current = std::mem::take(&mut server_snapshots.current);
+    let unchanged = match server_snapshots.history.latest() {
+        Some((_, latest)) => **latest == current && !relevant_entities.changed,
+        None => current.entities.is_empty(),
+    };
+    if !unchanged {
+        server_snapshots.history.push(current);
+        server_snapshots.latest_time = SnapshotTime {
+            time: now,
+            previous_time: last_frame_time,
+        };
+    }
+}
+fn send_snapshots(
+    mut server_snapshots: ResMut<ServerSnapshots>,
+    mut server_data_uploader: ResMut<ServerDataUploader>,
+    mut replication_priorities: ResMut<ReplicationPriorities>,
+    relevant_entities: Res<RelevantEntities>,
+    replication_config: Res<ReplicationConfig>,
+    time: Res<Time<Real>>,
+) {
+    let server_snapshots = server_snapshots.as_mut();
+    let now = time.elapsed_seconds_f64();
+    let Some((latest_id, latest)) = server_snapshots.history.latest() else {
+        return;
+    };
+    let latest = latest.clone();
+    let latest = &latest;
+    let mut messages: HashMap<Option<u32>, Bytes> = HashMap::new();
+    for (id, client) in server_snapshots.clients.iter_mut() {
+        if client.sent == Some(latest_id) && !client.pending {
+            continue;
+        }
+        let view = relevant_entities.filter(*id, latest);
+        let (view, complete) = match replication_config.bandwidth_budget {
+            Some(budget) => replication_priorities.limit(
+                *id,
+                &view,
+                client.views.latest().map(|(_, sent)| sent),
+                budget,
+            ),
+            None => (view, true),
+        };
+        let view_id = match complete {
+            true => latest_id,
+            false => server_snapshots.history.reserve_id(),
+        };
+        let baseline = client
+            .acked
+            .and_then(|acked| Some((acked, client.views.get(acked)?)));
+        let shared = Arc::ptr_eq(&view, latest)
+            && baseline.is_none_or(|(baseline_id, baseline)| {
+                server_snapshots
+                    .history
+                    .get(baseline_id)
+                    .is_some_and(|shared| Arc::ptr_eq(shared, baseline))
+            });
+        let build = || -> Bytes {
+            let mut message = SnapshotMessage::new(
+                view_id,
+                server_snapshots.latest_time,
+                baseline.map(|(baseline_id, baseline)| (baseline_id, baseline.as_ref())),
+                &view,
+            );
+            message.server_time = now;
+            bincode::serialize(&message).unwrap().into()
+        };
+        let bytes = match shared {
+            true => messages
+                .entry(baseline.map(|(baseline_id, _)| baseline_id))
+                .or_insert_with(build)
+                .clone(),
+            false => build(),
+        };
+        server_data_uploader.list.push((
+            Recipient::Single { id: *id },
+            DataPacket {
+                identifier: REPLICATION_IDENTIFIER,
+                bytes,
+            },
+        ));
+        client.views.insert(view_id, view);
+        client.sent = Some(latest_id);
+        client.pending = !complete;
+    }
+}
+pub(crate) fn apply_snapshots(
+    world: &mut World,
+    mut client_data_read_reader: Local<ManualEventReader<ClientDataReadEvent>>,
+) {
+    let messages: Vec<SnapshotMessage> = client_data_read_reader
+        .read(world.resource::<Events<ClientDataReadEvent>>())
+        .filter(|event| event.data_packet.identifier == REPLICATION_IDENTIFIER)
+        .filter_map(
+            |event| match bincode::deserialize(&event.data_packet.bytes) {
+                Ok(message) => Some(message),
+                Err(err) => {
+                    println!("Failed to deserialize a snapshot: {err:#?}");
+                    None
+                }
+            },
+        )
+        .collect();
+    if messages.is_empty() {
+        return;
+    }
+    let network_entity_map = world.resource::<NetworkEntityMap>().clone();
+    world.resource_scope(|world, registry: Mut<ReplicationRegistry>| {
+        world.resource_scope(|world, mut client_snapshots: Mut<ClientSnapshots>| {
+            for message in messages {
+                let time = message.time;
+                let local_time = world.resource::<Time<Real>>().elapsed_seconds_f64();
+                world
+                    .resource_mut::<ServerClock>()
+                    .observe(message.server_time, local_time);
+                let ack = receive_snapshot(&mut client_snapshots, message);
+                if let SnapshotAck(Some(_)) = ack {
+                    let (_, snapshot) = client_snapshots.history.latest().unwrap();
+                    let snapshot = snapshot.clone();
+                    network_entity_map.scope(|| {
+                        apply_snapshot(
+                            world,
+                            &registry,
+                            &network_entity_map,
+                            &client_snapshots.applied,
+                            &snapshot,
+                            time,
+                        )
+                    });
+                    client_snapshots.applied = snapshot;
+                }
+                let mut client_data_uploader = world.resource_mut::<ClientDataUploader>();
+                if client_data_uploader.is_connected() {
+                    let _ = client_data_uploader.upload(ack);
+                }
+            }
+        });
+    });
+}
+fn receive_snapshot(
+    client_snapshots: &mut ClientSnapshots,
+    message: SnapshotMessage,
+) -> SnapshotAck {
+    let id = message.id;
+    let baseline_id = message.baseline;
+    let snapshot = match baseline_id {
+        Some(baseline_id) => client_snapshots
+            .history
+            .get(baseline_id)
+            .cloned()
+            .and_then(|baseline| message.apply(&baseline)),
+        None => message.apply(&Snapshot::default()),
+    };
+    let Some(snapshot) = snapshot else {
+        println!("Received a snapshot with an unknown baseline, requesting a full snapshot");
+        client_snapshots.history.clear();
+        return SnapshotAck(None);
+    };
+    if let Some(baseline_id) = baseline_id {
+        client_snapshots.history.retain_from(baseline_id);
+    }
+    client_snapshots.history.insert(id, Arc::new(snapshot));
+    SnapshotAck(Some(id))
+}
+fn apply_snapshot(
+    world: &mut World,
+    registry: &ReplicationRegistry,
+    network_entity_map: &NetworkEntityMap,
+    applied: &Snapshot,
+    snapshot: &Snapshot,
+    time: SnapshotTime,
+) {
+    for network_entity in snapshot.entities.keys() {
+        if network_entity_map.to_local(*network_entity).is_none() {
+            let entity = world.spawn((Replicated, *network_entity)).id();
+            network_entity_map.insert(*network_entity, entity);
+        }
+    }
+    for (network_entity, components) in snapshot.entities.iter() {
+        let Some(mut entity) = network_entity_map
+            .to_local(*network_entity)
+            .and_then(|entity| world.get_entity_mut(entity))
+        else {
+            continue;
+        };
+        let applied_components = applied.entities.get(network_entity);
+        let predicted = entity.contains::<Predicted>();
+        for (component_index, bytes) in components.iter() {
+            if applied_components.and_then(|applied| applied.get(component_index)) == Some(bytes)
+                && (!predicted || *component_index == PARENT_COMPONENT)
+            {
+                continue;
+            }
+            if *component_index == PARENT_COMPONENT {
+                let parent = bincode::deserialize(bytes)
+                    .ok()
+                    .and_then(|parent| network_entity_map.to_local(parent));
+                match parent {
+                    Some(parent) => {
+                        entity.set_parent(parent);
+                    }
+                    None => {
+                        entity.remove_parent();
+                    }
+                }
+                continue;
+            }
+            let Some(component_fns) = registry.get(*component_index) else {
+                continue;
+            };
+            if let Err(err) = (component_fns.insert)(&mut entity, bytes, time) {
+                println!("Failed to deserialize a replicated component: {err:#?}");
+            }
+        }
+        for component_index in applied_components.into_iter().flat_map(|c| c.keys()) {
+            if components.contains_key(component_index) {
+                continue;
+            }
+            if *component_index == PARENT_COMPONENT {
+                entity.remove_parent();
+                continue;
+            }
+            if let Some(component_fns) = registry.get(*component_index) {
+                (component_fns.remove)(&mut entity);
+            }
+        }
+    }
+    for network_entity in applied.entities.keys() {
+        if snapshot.entities.contains_key(network_entity) {
+            continue;
+        }
+        let entity = network_entity_map
+            .remove(*network_entity)
+            .and_then(|entity| world.get_entity_mut(entity));
+        if let Some(entity) = entity {
+            entity.despawn_recursive();
+        }
+    }
+}
+fn despawn_on_disconnect(
+    mut commands: Commands,
+    mut server_connection_change_reader: EventReader<ServerConnectionChangeEvent>,
+    network_entity_map: Res<NetworkEntityMap>,
+    parents: Query<&Parent>,
+    mut client_snapshots: ResMut<ClientSnapshots>,
+    mut server_clock: ResMut<ServerClock>,
+) {
+    for event in server_connection_change_reader.read() {
+        if let ConnectionChange::Disconnected = event.connection_change {
+            let entities: HashSet<Entity> = network_entity_map.drain().into_iter().collect();
+            for entity in entities.iter() {
+                if parents
+                    .get(*entity)
+                    .is_ok_and(|parent| entities.contains(&parent.get()))
+                {
+                    continue;
+                }
+                commands.entity(*entity).despawn_recursive();
+            }
+            client_snapshots.history.clear();
+            client_snapshots.applied = Arc::new(Snapshot::default());
+            server_clock.reset();
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    has_client_config, has_server_config,
+    replication::{apply_snapshots, ReplicationSet},
+    ClientDataReadEvent, ConnectionChange, Identify, IntergressType, NetworkEntityMap,
+    PlayerIntergressEvent, Recipient, ServerConnectionChangeEvent, ServerDataUploader,
+    RESOURCE_IDENTIFIER,
+};
+use bevy::{ecs::event::ManualEventReader, prelude::*, utils::HashSet};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+#[derive(Serialize, Deserialize, Debug)]
+struct ResourceMessage {
+    index: u16,
+    bytes: Option<Vec<u8>>,
+}
+impl Identify for ResourceMessage {
+    fn get_identifier(&self) -> u32 {
+        RESOURCE_IDENTIFIER
+    }
+}
+#[derive(Resource, Default)]
+struct ResourceRegistry {
+    resources: Vec<ResourceFns>,
+}
+#[derive(Resource)]
+struct ResourceSender<R> {
+    index: u16,
+    filter: Option<fn(u32, &R) -> bool>,
+    clients: HashSet<u32>,
+    holders: HashSet<u32>,
+}
+struct ResourceFns {
+    insert: fn(&mut World, &[u8]) -> bincode::Result<()>,
+    remove: fn(&mut World),
+}
+pub(crate) fn replicate_resource<R: Resource + Serialize + DeserializeOwned>(
+    app: &mut App,
+    filter: Option<fn(u32, &R) -> bool>,
+) {
+    if !app.world().contains_resource::<ResourceRegistry>() {
+        app.init_resource::<ResourceRegistry>().add_systems(
+            PreUpdate,
+            apply_resources
+                .in_set(ReplicationSet::Receive)
+                .after(apply_snapshots)
+                .run_if(has_client_config),
+        );
+    }
+    let mut registry = app.world_mut().resource_mut::<ResourceRegistry>();
+    let index = registry.resources.len() as u16;
+    registry.resources.push(ResourceFns {
+        insert: insert_resource::<R>,
+        remove: remove_resource::<R>,
+    });
+    app.insert_resource(ResourceSender::<R> {
+        index,
+        filter,
+        clients: HashSet::new(),
+        holders: HashSet::new(),
+    })
+    .add_systems(PostUpdate, send_resource::<R>.run_if(has_server_config));
+}
+fn insert_resource<R: Resource + DeserializeOwned>(
+    world: &mut World,
+    bytes: &[u8],
+) -> bincode::Result<()> {
+    let resource: R = world.resource::<NetworkEntityMap>().deserialize(bytes)?;
+    world.insert_resource(resource);
+    Ok(())
+}
+fn remove_resource<R: Resource>(world: &mut World) {
+    world.remove_resource::<R>();
+}
+fn send_resource<R: Resource + Serialize>(
+    mut player_intergress_reader: EventReader<PlayerIntergressEvent>,
+    resource: Option<Res<R>>,
+    mut server_data_uploader: ResMut<ServerDataUploader>,
+    mut sender: ResMut<ResourceSender<R>>,
+) {
+    let sender = sender.as_mut();
+    let index = sender.index;
+    let mut joined = vec![];
+    for event in player_intergress_reader.read() {
+        match event.intergress_type {
+            IntergressType::Joined => {
+                sender.clients.insert(event.id);
+                joined.push(event.id);
+            }
+            IntergressType::Left => {
+                sender.clients.remove(&event.id);
+                sender.holders.remove(&event.id);
+            }
+        }
+    }
+    let Some(resource) = resource else {
+        for id in sender.holders.drain() {
+            server_data_uploader.upload(
+                ResourceMessage { index, bytes: None },
+                Recipient::Single { id },
+            );
+        }
+        return;
+    };
+    let targets: Vec<u32> = match resource.is_changed() {
+        true => sender.clients.iter().copied().collect(),
+        false => joined,
+    };
+    if targets.is_empty() {
+        return;
+    }
+    let bytes = match bincode::serialize(resource.as_ref()) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            println!("Failed to serialize a replicated resource: {err:#?}");
+            return;
+        }
+    };
+    for id in targets {
+        if sender.filter.is_none_or(|filter| filter(id, &resource)) {
+            sender.holders.insert(id);
+            server_data_uploader.upload(
+                ResourceMessage {
+                    index,
+                    bytes: Some(bytes.clone()),
+                },
+                Recipient::Single { id },
+            );
+        } else if sender.holders.remove(&id) {
+            server_data_uploader.upload(
+                ResourceMessage { index, bytes: None },
+                Recipient::Single { id },
+            );
+        }
+    }
+}
+fn apply_resources(
+    world: &mut World,
+    mut client_data_read_reader: Local<ManualEventReader<ClientDataReadEvent>>,
+    mut server_connection_change_reader: Local<ManualEventReader<ServerConnectionChangeEvent>>,
+) {
+    let disconnected = server_connection_change_reader
+        .read(world.resource::<Events<ServerConnectionChangeEvent>>())
+        .any(|event| matches!(event.connection_change, ConnectionChange::Disconnected));
+    let messages: Vec<ResourceMessage> = client_data_read_reader
+        .read(world.resource::<Events<ClientDataReadEvent>>())
+        .filter(|event| event.data_packet.identifier == RESOURCE_IDENTIFIER)
+        .filter_map(
+            |event| match bincode::deserialize(&event.data_packet.bytes) {
+                Ok(message) => Some(message),
+                Err(err) => {
+                    println!("Failed to deserialize a replicated resource: {err:#?}");
+                    None
+                }
+            },
+        )
+        .collect();
+    world.resource_scope(|world, registry: Mut<ResourceRegistry>| {
+        if disconnected {
+            for resource_fns in registry.resources.iter() {
+                (resource_fns.remove)(world);
+            }
+        }
+        for message in messages {
+            let Some(resource_fns) = registry.resources.get(message.index as usize) else {
+                continue;
+            };
+            match message.bytes {
+                Some(bytes) => {
+                    if let Err(err) = (resource_fns.insert)(world, &bytes) {
+                        println!("Failed to deserialize a replicated resource: {err:#?}");
+                    }
+                }
+                None => (resource_fns.remove)(world),
+            }
+        }
+    });
+}
@@ -0,0 +1,735 @@
+use crate::{
+    has_client_config, has_server_config, ClientDataReadEvent, ClientDataUploader,
+    ConnectionChange, Identify, IntergressType, NetworkInput, NetworkInputData,
+    PlayerIntergressEvent, Recipient, ServerConnectionChangeEvent, ServerDataReadEvent,
+    ServerDataUploader, ROLLBACK_IDENTIFIER,
+};
+use bevy::{
+    ecs::schedule::{InternedScheduleLabel, ScheduleLabel},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    any::Any,
+    collections::{BTreeMap, VecDeque},
+};
+type SavedState = Box<dyn Any + Send + Sync>;
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Rollback;
+#[derive(Resource)]
+pub struct RollbackConfig {
+    pub input_delay: u32,
+    pub max_prediction: u32,
+}
+impl Default for RollbackConfig {
+    fn default() -> Self {
+        RollbackConfig {
+            input_delay: 2,
+            max_prediction: 8,
+        }
+    }
+}
+#[derive(Resource, Clone, Debug)]
+pub struct RollbackInputs<I> {
+    pub frame: u32,
+    pub local_player: u32,
+    pub inputs: Vec<(u32, I)>,
+}
+impl<I> RollbackInputs<I> {
+    pub fn get(&self, player: u32) -> Option<&I> {
+        self.inputs
+            .iter()
+            .find(|(id, _)| *id == player)
+            .map(|(_, input)| input)
+    }
+}
+pub trait RollbackApp {
+    fn rollback<I: NetworkInputData + PartialEq>(
+        &mut self,
+        schedule: impl ScheduleLabel,
+    ) -> &mut Self;
+    fn rollback_component<C: Component + Clone>(&mut self) -> &mut Self;
+    fn rollback_resource<R: Resource + Clone>(&mut self) -> &mut Self;
+}
+impl RollbackApp for App {
+    fn rollback<I: NetworkInputData + PartialEq>(
+        &mut self,
+        schedule: impl ScheduleLabel,
+    ) -> &mut Self {
+        let schedule = schedule.intern();
+        self.init_schedule(schedule)
+            .init_resource::<RollbackConfig>()
+            .init_resource::<RollbackRegistry>()
+            .init_resource::<NetworkInput<I>>()
+            .insert_resource(RollbackSession::<I>::new(schedule))
+            .insert_resource(RollbackRelay {
+                players: HashMap::new(),
+                latest_frame: 0,
+            })
+            .add_systems(FixedUpdate, advance_rollback::<I>)
+            .add_systems(
+                PreUpdate,
+                receive_rollback_messages::<I>.run_if(has_client_config),
+            )
+            .add_systems(
+                FixedUpdate,
+                send_rollback_inputs::<I>
+                    .after(advance_rollback::<I>)
+                    .run_if(has_client_config),
+            )
+            .add_systems(
+                PreUpdate,
+                relay_rollback_inputs::<I>.run_if(has_server_config),
+            )
+    }
+    fn rollback_component<C: Component + Clone>(&mut self) -> &mut Self {
+        self.init_resource::<RollbackRegistry>();
+        self.world_mut()
+            .resource_mut::<RollbackRegistry>()
+            .states
+            .push(StateFns {
+                save: save_component::<C>,
+                restore: restore_component::<C>,
+            });
+        self
+    }
+    fn rollback_resource<R: Resource + Clone>(&mut self) -> &mut Self {
+        self.init_resource::<RollbackRegistry>();
+        self.world_mut()
+            .resource_mut::<RollbackRegistry>()
+            .states
+            .push(StateFns {
+                save: save_resource::<R>,
+                restore: restore_resource::<R>,
+            });
+        self
+    }
+}
+#[derive(Serialize, Deserialize, Debug)]
+enum RollbackMessage<I> {
+    Start {
+        player: u32,
+        frame: u32,
+        players: Vec<(u32, u32, u32)>,
+    },
+    Joined {
+        player: u32,
+        frame: u32,
+    },
+    Left {
+        player: u32,
+    },
+    Input {
+        player: u32,
+        frame: u32,
+        input: I,
+    },
+}
+impl<I> Identify for RollbackMessage<I> {
+    fn get_identifier(&self) -> u32 {
+        ROLLBACK_IDENTIFIER
+    }
+}
+#[derive(Resource, Default)]
+struct RollbackRegistry {
+    states: Vec<StateFns>,
+}
+struct StateFns {
+    save: fn(&mut World) -> SavedState,
+    restore: fn(&mut World, &SavedState),
+}
+fn save_component<C: Component + Clone>(world: &mut World) -> SavedState {
+    let saved: HashMap<Entity, C> = world
+        .query_filtered::<(Entity, &C), With<Rollback>>()
+        .iter(world)
+        .map(|(entity, component)| (entity, component.clone()))
+        .collect();
+    Box::new(saved)
+}
+fn restore_component<C: Component + Clone>(world: &mut World, saved: &SavedState) {
+    let Some(saved) = saved.downcast_ref::<HashMap<Entity, C>>() else {
+        return;
+    };
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, With<Rollback>>()
+        .iter(world)
+        .collect();
+    for entity in entities {
+        let mut entity = world.entity_mut(entity);
+        match saved.get(&entity.id()) {
+            Some(component) => {
+                entity.insert(component.clone());
+            }
+            None => {
+                entity.remove::<C>();
+            }
+        }
+    }
+}
+fn save_resource<R: Resource + Clone>(world: &mut World) -> SavedState {
+    Box::new(world.get_resource::<R>().cloned())
+}
+fn restore_resource<R: Resource + Clone>(world: &mut World, saved: &SavedState) {
+    match saved.downcast_ref::<Option<R>>() {
+        Some(Some(resource)) => world.insert_resource(resource.clone()),
+        Some(None) => {
+            world.remove_resource::<R>();
+        }
+        None => {}
+    }
+}
+struct PlayerInputs<I> {
+    start: u32,
+    confirmed_until: u32,
+    confirmed: BTreeMap<u32, I>,
+    used: BTreeMap<u32, I>,
+}
+impl<I: NetworkInputData> PlayerInputs<I> {
+    fn new(start: u32) -> Self {
+        PlayerInputs {
+            start,
+            confirmed_until: start,
+            confirmed: BTreeMap::new(),
+            used: BTreeMap::new(),
+        }
+    }
+    fn confirm(&mut self, frame: u32, input: I) {
+        self.confirmed.insert(frame, input);
+        while self.confirmed.contains_key(&self.confirmed_until) {
+            self.confirmed_until += 1;
+        }
+    }
+    fn input(&self, frame: u32) -> I {
+        self.confirmed
+            .range(..=frame)
+            .next_back()
+            .map(|(_, input)| input.clone())
+            .unwrap_or_default()
+    }
+    fn prune(&mut self, frame: u32) {
+        let keep_from = self
+            .confirmed
+            .range(..frame)
+            .next_back()
+            .map(|(kept, _)| *kept)
+            .unwrap_or(frame);
+        self.confirmed = self.confirmed.split_off(&keep_from);
+        self.used = self.used.split_off(&frame);
+    }
+}
+#[derive(Resource)]
+pub(crate) struct RollbackSession<I> {
+    schedule: InternedScheduleLabel,
+    local_player: Option<u32>,
+    frame: u32,
+    players: BTreeMap<u32, PlayerInputs<I>>,
+    snapshots: BTreeMap<u32, Vec<SavedState>>,
+    rollback_to: Option<u32>,
+    outgoing: VecDeque<(u32, I)>,
+}
+impl<I: NetworkInputData + PartialEq> RollbackSession<I> {
+    fn new(schedule: InternedScheduleLabel) -> Self {
+        RollbackSession {
+            schedule,
+            local_player: None,
+            frame: 0,
+            players: BTreeMap::new(),
+            snapshots: BTreeMap::new(),
+            rollback_to: None,
+            outgoing: VecDeque::new(),
+        }
+    }
+    fn start(&mut self, player: u32, frame: u32, players: Vec<(u32, u32, u32)>, input_delay: u32) {
+        self.local_player = Some(player);
+        self.frame = frame;
+        // Inputs that were relayed before we joined never reach us, so each player is only
+        // confirmed from the first frame we'll actually receive from them.
+        self.players = players
+            .into_iter()
+            .map(|(player, start, next_frame)| {
+                let mut inputs = PlayerInputs::new(start);
+                inputs.confirmed_until = next_frame.max(start);
+                (player, inputs)
+            })
+            .collect();
+        self.snapshots.clear();
+        self.rollback_to = None;
+        self.outgoing.clear();
+        let mut local = PlayerInputs::new(frame);
+        for frame in frame..frame + input_delay {
+            local.confirm(frame, I::default());
+            self.outgoing.push_back((frame, I::default()));
+        }
+        self.players.insert(player, local);
+    }
+    fn stop(&mut self) {
+        self.local_player = None;
+        self.players.clear();
+        self.snapshots.clear();
+        self.rollback_to = None;
+        self.outgoing.clear();
+    }
+    fn add_player(&mut self, player: u32, start: u32) {
+        self.players.insert(player, PlayerInputs::new(start));
+        if start < self.frame {
+            self.request_rollback(start);
+        }
+    }
+    fn receive(&mut self, player: u32, frame: u32, input: I) {
+        let Some(inputs) = self.players.get_mut(&player) else {
+            return;
+        };
+        if frame < inputs.confirmed_until {
+            return;
+        }
+        let mispredicted = inputs.used.get(&frame).is_some_and(|used| *used != input);
+        inputs.confirm(frame, input);
+        if mispredicted {
+            self.request_rollback(frame);
+        }
+    }
+    fn request_rollback(&mut self, frame: u32) {
+        self.rollback_to = Some(self.rollback_to.map_or(frame, |to| to.min(frame)));
+    }
+    fn confirmed_frame(&self) -> u32 {
+        self.players
+            .values()
+            .map(|inputs| inputs.confirmed_until)
+            .min()
+            .unwrap_or(self.frame)
+    }
+    fn frame_inputs(&mut self, frame: u32) -> Vec<(u32, I)> {
+        let mut inputs = vec![];
+        for (player, player_inputs) in self.players.iter_mut() {
+            if frame < player_inputs.start {
+                continue;
+            }
+            let input = player_inputs.input(frame);
+            player_inputs.used.insert(frame, input.clone());
+            inputs.push((*player, input));
+        }
+        inputs
+    }
+}
+fn advance_rollback<I: NetworkInputData + PartialEq>(world: &mut World) {
+    let Some(local_player) = world.resource::<RollbackSession<I>>().local_player else {
+        return;
+    };
+    let (input_delay, max_prediction) = {
+        let rollback_config = world.resource::<RollbackConfig>();
+        (rollback_config.input_delay, rollback_config.max_prediction)
+    };
+    let input = world.resource::<NetworkInput<I>>().0.clone();
+    let mut session = world.resource_mut::<RollbackSession<I>>();
+    if session.frame >= session.confirmed_frame() + max_prediction {
+        return;
+    }
+    let frame = session.frame;
+    if let Some(local) = session.players.get_mut(&local_player) {
+        local.confirm(frame + input_delay, input.clone());
+    }
+    session.outgoing.push_back((frame + input_delay, input));
+    if let Some(rollback_to) = session.rollback_to.take() {
+        if rollback_to < frame {
+            match session.snapshots.contains_key(&rollback_to) {
+                true => {
+                    restore_snapshot::<I>(world, rollback_to);
+                    for frame in rollback_to..frame {
+                        simulate_frame::<I>(world, frame, local_player);
+                    }
+                }
+                false => {
+                    println!("Couldn't roll back to frame {rollback_to} because its snapshot is no longer saved");
+                }
+            }
+        }
+    }
+    simulate_frame::<I>(world, frame, local_player);
+    let mut session = world.resource_mut::<RollbackSession<I>>();
+    session.frame = frame + 1;
+    let confirmed_frame = session.confirmed_frame().min(session.frame);
+    session.snapshots = session.snapshots.split_off(&confirmed_frame);
+    for inputs in session.players.values_mut() {
+        inputs.prune(confirmed_frame);
+    }
+}
+fn simulate_frame<I: NetworkInputData + PartialEq>(
+    world: &mut World,
+    frame: u32,
+    local_player: u32,
+) {
+    let snapshot = world.resource_scope(|world, registry: Mut<RollbackRegistry>| {
+        registry
+            .states
+            .iter()
+            .map(|state| (state.save)(world))
+            .collect()
+    });
+    let mut session = world.resource_mut::<RollbackSession<I>>();
+    session.snapshots.insert(frame, snapshot);
+    let inputs = session.frame_inputs(frame);
+    let schedule = session.schedule;
+    world.insert_resource(RollbackInputs {
+        frame,
+        local_player,
+        inputs,
+    });
+    world.run_schedule(schedule);
+}
+fn restore_snapshot<I: NetworkInputData + PartialEq>(world: &mut World, frame: u32) {
+    world.resource_scope(|world, mut session: Mut<RollbackSession<I>>| {
+        let Some(snapshot) = session.snapshots.remove(&frame) else {
+            return;
+        };
+        world.resource_scope(|world, registry: Mut<RollbackRegistry>| {
+            for (state, saved) in registry.states.iter().zip(snapshot.iter()) {
+                (state.restore)(world, saved);
+            }
+        });
+        session.snapshots.insert(frame, snapshot);
+    });
+}
+fn send_rollback_inputs<I: NetworkInputData + PartialEq>(
+    mut session: ResMut<RollbackSession<I>>,
+    client_data_uploader: Option<ResMut<ClientDataUploader>>,
+) {
+    let Some(mut client_data_uploader) = client_data_uploader else {
+        return;
+    };
+    let Some(player) = session.local_player else {
+        return;
+    };
+    while let Some((frame, input)) = session.outgoing.pop_front() {
+        let _ = client_data_uploader.upload(RollbackMessage::Input {
+            player,
+            frame,
+            input,
+        });
+    }
+}
+fn receive_rollback_messages<I: NetworkInputData + PartialEq>(
+    mut client_data_reader: EventReader<ClientDataReadEvent>,
+    mut server_connection_change_reader: EventReader<ServerConnectionChangeEvent>,
+    mut session: ResMut<RollbackSession<I>>,
+    rollback_config: Res<RollbackConfig>,
+) {
+    for event in server_connection_change_reader.read() {
+        if let ConnectionChange::Disconnected = event.connection_change {
+            session.stop();
+        }
+    }
+    for event in client_data_reader.read() {
+        if event.data_packet.identifier != ROLLBACK_IDENTIFIER {
+            continue;
+        }
+        let message: RollbackMessage<I> = match bincode::deserialize(&event.data_packet.bytes) {
+            Ok(message) => message,
+            Err(err) => {
+                println!("Failed to deserialize a rollback message: {err:#?}");
+                continue;
+            }
+        };
+        match message {
+            RollbackMessage::Start {
+                player,
+                frame,
+                players,
+            } => session.start(player, frame, players, rollback_config.input_delay),
+            RollbackMessage::Joined { player, frame } => session.add_player(player, frame),
+            RollbackMessage::Left { player } => {
+                session.players.remove(&player);
+            }
+            RollbackMessage::Input {
+                player,
+                frame,
+                input,
+            } => session.receive(player, frame, input),
+        }
+    }
+}
+#[derive(Resource)]
+struct RollbackRelay {
+    players: HashMap<u32, (u32, u32)>,
+    latest_frame: u32,
+}
+fn relay_rollback_inputs<I: NetworkInputData + PartialEq>(
+    mut server_data_reader: EventReader<ServerDataReadEvent>,
+    mut player_intergress_reader: EventReader<PlayerIntergressEvent>,
+    mut server_data_uploader: ResMut<ServerDataUploader>,
+    mut relay: ResMut<RollbackRelay>,
+) {
+    for event in player_intergress_reader.read() {
+        match event.intergress_type {
+            IntergressType::Joined => {
+                let frame = relay.latest_frame;
+                let players = relay
+                    .players
+                    .iter()
+                    .map(|(player, (start, next_frame))| (*player, *start, *next_frame))
+                    .collect();
+                server_data_uploader.upload(
+                    RollbackMessage::<I>::Start {
+                        player: event.id,
+                        frame,
+                        players,
+                    },
+                    Recipient::Single { id: event.id },
+                );
+                server_data_uploader.upload(
+                    RollbackMessage::<I>::Joined {
+                        player: event.id,
+                        frame,
+                    },
+                    Recipient::AllExcept { id: event.id },
+                );
+                relay.players.insert(event.id, (frame, frame));
+            }
+            IntergressType::Left => {
+                if relay.players.remove(&event.id).is_some() {
+                    server_data_uploader.upload(
+                        RollbackMessage::<I>::Left { player: event.id },
+                        Recipient::AllExcept { id: event.id },
+                    );
+                }
+            }
+        }
+    }
+    for event in server_data_reader.read() {
+        if event.data_packet.identifier != ROLLBACK_IDENTIFIER {
+            continue;
+        }
+        let Ok(RollbackMessage::<I>::Input { frame, input, .. }) =
+            bincode::deserialize(&event.data_packet.bytes)
+        else {
+            continue;
+        };
+        let Some((_, next_frame)) = relay.players.get_mut(&event.id) else {
+            continue;
+        };
+        *next_frame = (*next_frame).max(frame.saturating_add(1));
+        relay.latest_frame = relay.latest_frame.max(frame);
+        server_data_uploader.upload(
+            RollbackMessage::Input {
+                player: event.id,
+                frame,
+                input,
+            },
+            Recipient::AllExcept { id: event.id },
+        );
+    }
+}
+pub struct RollbackHarness<I> {
+    pub delay: u32,
+    peers: Vec<App>,
+    players: Vec<(u32, u32)>,
+    latest_frame: u32,
+    in_flight: VecDeque<(u32, usize, u32, u32, I)>,
+    step: u32,
+}
+impl<I: NetworkInputData + PartialEq> RollbackHarness<I> {
+    pub fn new(peers: Vec<App>, delay: u32) -> Self {
+        let mut harness = RollbackHarness {
+            delay,
+            players: vec![(0, 0); peers.len()],
+            peers,
+            latest_frame: 0,
+            in_flight: VecDeque::new(),
+            step: 0,
+        };
+        let players: Vec<(u32, u32, u32)> = (0..harness.peers.len() as u32)
+            .map(|player| (player, 0, 0))
+            .collect();
+        for (player, peer) in harness.peers.iter_mut().enumerate() {
+            let input_delay = peer.world().resource::<RollbackConfig>().input_delay;
+            let others = players
+                .iter()
+                .filter(|(other, ..)| *other != player as u32)
+                .copied()
+                .collect();
+            peer.world_mut().resource_mut::<RollbackSession<I>>().start(
+                player as u32,
+                0,
+                others,
+                input_delay,
+            );
+        }
+        harness
+    }
+    pub fn advance(&mut self, inputs: &[I]) {
+        let peer_count = self.peers.len();
+        for (player, peer) in self.peers.iter_mut().enumerate() {
+            if let Some(input) = inputs.get(player) {
+                peer.world_mut().resource_mut::<NetworkInput<I>>().0 = input.clone();
+            }
+            advance_rollback::<I>(peer.world_mut());
+            let mut session = peer.world_mut().resource_mut::<RollbackSession<I>>();
+            while let Some((frame, input)) = session.outgoing.pop_front() {
+                let (_, next_frame) = &mut self.players[player];
+                *next_frame = (*next_frame).max(frame + 1);
+                self.latest_frame = self.latest_frame.max(frame);
+                for other in (0..peer_count).filter(|other| *other != player) {
+                    self.in_flight.push_back((
+                        self.step + self.delay,
+                        other,
+                        player as u32,
+                        frame,
+                        input.clone(),
+                    ));
+                }
+            }
+        }
+        self.step += 1;
+        while self
+            .in_flight
+            .front()
+            .is_some_and(|(deliver_at, ..)| *deliver_at <= self.step)
+        {
+            let (_, peer, player, frame, input) = self.in_flight.pop_front().unwrap();
+            self.peers[peer]
+                .world_mut()
+                .resource_mut::<RollbackSession<I>>()
+                .receive(player, frame, input);
+        }
+    }
+    pub fn join(&mut self, mut peer: App) {
+        let player = self.peers.len() as u32;
+        let frame = self.latest_frame;
+        let players = self
+            .players
+            .iter()
+            .enumerate()
+            .map(|(other, (start, next_frame))| (other as u32, *start, *next_frame))
+            .collect();
+        let input_delay = peer.world().resource::<RollbackConfig>().input_delay;
+        peer.world_mut().resource_mut::<RollbackSession<I>>().start(
+            player,
+            frame,
+            players,
+            input_delay,
+        );
+        for other in self.peers.iter_mut() {
+            other
+                .world_mut()
+                .resource_mut::<RollbackSession<I>>()
+                .add_player(player, frame);
+        }
+        self.players.push((frame, frame));
+        self.peers.push(peer);
+    }
+    pub fn frame(&self, peer: usize) -> u32 {
+        self.peers[peer]
+            .world()
+            .resource::<RollbackSession<I>>()
+            .frame
+    }
+    pub fn peer(&self, peer: usize) -> &App {
+        &self.peers[peer]
+    }
+    pub fn peer_mut(&mut self, peer: usize) -> &mut App {
+        &mut self.peers[peer]
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+    struct Simulation;
+    #[derive(Resource, Clone, Default, PartialEq, Debug)]
+    struct Positions([i64; 2]);
+    #[derive(Resource, Clone, Default)]
+    struct Trail(Vec<(u32, [i64; 2])>);
+    #[derive(Resource, Default)]
+    struct SimulatedFrames(u32);
+    const INPUT_DELAY: u32 = 2;
+    const MAX_PREDICTION: u32 = 8;
+    fn step(positions: &mut Positions, inputs: &[(u32, i32)]) {
+        for (player, input) in inputs {
+            let position = &mut positions.0[*player as usize];
+            *position = position.wrapping_mul(3).wrapping_add(*input as i64);
+        }
+    }
+    fn simulate(
+        rollback_inputs: Res<RollbackInputs<i32>>,
+        mut positions: ResMut<Positions>,
+        mut trail: ResMut<Trail>,
+        mut simulated_frames: ResMut<SimulatedFrames>,
+    ) {
+        step(&mut positions, &rollback_inputs.inputs);
+        trail.0.push((rollback_inputs.frame, positions.0));
+        simulated_frames.0 += 1;
+    }
+    fn peer() -> App {
+        let mut app = App::new();
+        app.insert_resource(RollbackConfig {
+            input_delay: INPUT_DELAY,
+            max_prediction: MAX_PREDICTION,
+        })
+        .init_resource::<Positions>()
+        .init_resource::<Trail>()
+        .init_resource::<SimulatedFrames>()
+        .rollback::<i32>(Simulation)
+        .rollback_resource::<Positions>()
+        .rollback_resource::<Trail>()
+        .add_systems(Simulation, simulate);
+        app
+    }
+    fn input(step: u32, player: u32) -> i32 {
+        match step < 30 {
+            true => ((step / 3 + player) % 4) as i32,
+            false => 0,
+        }
+    }
+    #[test]
+    fn peers_converge_after_mispredictions() {
+        let steps = 45;
+        let mut harness = RollbackHarness::<i32>::new(vec![peer(), peer()], 4);
+        for step in 0..steps {
+            harness.advance(&[input(step, 0), input(step, 1)]);
+        }
+        let mut expected = Positions::default();
+        for frame in 0..steps {
+            let inputs: Vec<(u32, i32)> = (0..2)
+                .map(|player| match frame.checked_sub(INPUT_DELAY) {
+                    Some(step) => (player, input(step, player)),
+                    None => (player, 0),
+                })
+                .collect();
+            step(&mut expected, &inputs);
+        }
+        for peer in 0..2 {
+            assert_eq!(harness.frame(peer), steps);
+            let world = harness.peer(peer).world();
+            assert!(world.resource::<SimulatedFrames>().0 > steps);
+            assert_eq!(world.resource::<Positions>(), &expected);
+        }
+    }
+    #[test]
+    fn late_joiner_keeps_up() {
+        let solo_steps = MAX_PREDICTION * 3;
+        let mut harness = RollbackHarness::<i32>::new(vec![peer()], 4);
+        for step in 0..solo_steps {
+            harness.advance(&[input(step, 0)]);
+        }
+        harness.join(peer());
+        let join_frame = harness.frame(1);
+        assert!(join_frame >= solo_steps);
+        let steps = 40;
+        for step in solo_steps..solo_steps + steps {
+            harness.advance(&[input(step, 0), input(step, 1)]);
+        }
+        assert_eq!(harness.frame(0), solo_steps + steps);
+        assert_eq!(harness.frame(1), join_frame + steps);
+        let joined_trail = &harness.peer(1).world().resource::<Trail>().0;
+        let trail = &harness.peer(0).world().resource::<Trail>().0;
+        let compared = joined_trail
+            .iter()
+            .filter_map(|(frame, positions)| {
+                let (_, other) = trail.iter().find(|(other, _)| other == frame)?;
+                Some((positions[1], other[1]))
+            })
+            .inspect(|(joined, other)| assert_eq!(joined, other))
+            .count();
+        assert!(compared >= steps as usize - 1);
+    }
+}
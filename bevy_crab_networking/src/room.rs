@@ -0,0 +1,255 @@
+use crate::{
+    has_server_config, is_internal_identifier, IntergressType, PlayerIntergressEvent, Recipient,
+    ServerDataReadEvent,
+};
+use bevy::{
+    ecs::schedule::{InternedScheduleLabel, ScheduleLabel},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+#[derive(Component, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InRoom(pub String);
+#[derive(Default, Clone, Debug)]
+pub struct ClientMetadata {
+    pub rooms: HashSet<String>,
+    pub values: HashMap<String, String>,
+}
+impl ClientMetadata {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|value| value.as_str())
+    }
+}
+#[derive(Resource, Default)]
+pub struct NetworkRooms {
+    rooms: HashMap<String, HashSet<u32>>,
+    clients: HashMap<u32, ClientMetadata>,
+    matches: HashSet<String>,
+}
+impl NetworkRooms {
+    pub fn join(&mut self, room: impl Into<String>, id: u32) {
+        let room = room.into();
+        self.clients
+            .entry(id)
+            .or_default()
+            .rooms
+            .insert(room.clone());
+        self.rooms.entry(room).or_default().insert(id);
+    }
+    pub fn leave(&mut self, room: &str, id: u32) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.rooms.remove(room);
+        }
+        let Some(members) = self.rooms.get_mut(room) else {
+            return;
+        };
+        members.remove(&id);
+        if members.is_empty() {
+            self.rooms.remove(room);
+        }
+    }
+    pub fn leave_all(&mut self, id: u32) {
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+        for room in std::mem::take(&mut client.rooms) {
+            self.leave(&room, id);
+        }
+    }
+    pub fn remove_room(&mut self, room: &str) {
+        self.matches.remove(room);
+        for id in self.rooms.remove(room).unwrap_or_default() {
+            if let Some(client) = self.clients.get_mut(&id) {
+                client.rooms.remove(room);
+            }
+        }
+    }
+    pub fn members(&self, room: &str) -> impl Iterator<Item = u32> + '_ {
+        self.rooms.get(room).into_iter().flatten().copied()
+    }
+    pub fn contains(&self, room: &str, id: u32) -> bool {
+        self.rooms
+            .get(room)
+            .is_some_and(|members| members.contains(&id))
+    }
+    pub fn rooms(&self) -> impl Iterator<Item = &str> {
+        self.rooms.keys().map(|room| room.as_str())
+    }
+    pub fn set_metadata(&mut self, id: u32, key: impl Into<String>, value: impl Into<String>) {
+        self.clients
+            .entry(id)
+            .or_default()
+            .values
+            .insert(key.into(), value.into());
+    }
+    pub fn remove_metadata(&mut self, id: u32, key: &str) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.values.remove(key);
+        }
+    }
+    pub fn metadata(&self, id: u32) -> Option<&ClientMetadata> {
+        self.clients.get(&id)
+    }
+    pub fn start_match(&mut self, room: impl Into<String>) {
+        self.matches.insert(room.into());
+    }
+    pub fn stop_match(&mut self, room: &str) {
+        self.matches.remove(room);
+    }
+    pub fn is_running(&self, room: &str) -> bool {
+        self.matches.contains(room)
+    }
+    pub fn matches(&self) -> impl Iterator<Item = &str> {
+        self.matches.iter().map(|room| room.as_str())
+    }
+}
+#[derive(Resource)]
+pub struct CurrentRoom {
+    name: String,
+    members: Vec<u32>,
+    messages: Vec<ServerDataReadEvent>,
+}
+impl CurrentRoom {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn members(&self) -> &[u32] {
+        &self.members
+    }
+    pub fn messages(&self) -> &[ServerDataReadEvent] {
+        &self.messages
+    }
+    pub fn contains(&self, in_room: &InRoom) -> bool {
+        in_room.0 == self.name
+    }
+    pub fn tag(&self) -> InRoom {
+        InRoom(self.name.clone())
+    }
+    pub fn recipient(&self) -> Recipient {
+        Recipient::Room(self.name.clone())
+    }
+}
+pub trait RoomApp {
+    fn room_schedule(&mut self, schedule: impl ScheduleLabel) -> &mut Self;
+}
+impl RoomApp for App {
+    fn room_schedule(&mut self, schedule: impl ScheduleLabel) -> &mut Self {
+        let schedule = schedule.intern();
+        self.init_schedule(schedule);
+        self.world_mut()
+            .resource_mut::<RoomSchedules>()
+            .schedules
+            .push(schedule);
+        self
+    }
+}
+#[derive(Resource, Default)]
+struct RoomSchedules {
+    schedules: Vec<InternedScheduleLabel>,
+    running: HashSet<String>,
+    inboxes: HashMap<String, Vec<ServerDataReadEvent>>,
+}
+pub(crate) struct RoomPlugin;
+impl Plugin for RoomPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkRooms>()
+            .init_resource::<RoomSchedules>()
+            .add_systems(
+                PreUpdate,
+                (remove_left_clients, route_room_messages)
+                    .chain()
+                    .run_if(has_server_config),
+            )
+            .add_systems(FixedUpdate, run_room_schedules.run_if(has_server_config));
+    }
+}
+fn route_room_messages(
+    mut server_data_read_reader: EventReader<ServerDataReadEvent>,
+    mut room_schedules: ResMut<RoomSchedules>,
+    network_rooms: Res<NetworkRooms>,
+) {
+    if room_schedules.schedules.is_empty() {
+        server_data_read_reader.clear();
+        return;
+    }
+    for event in server_data_read_reader.read() {
+        if is_internal_identifier(event.data_packet.identifier) {
+            continue;
+        }
+        let Some(client) = network_rooms.clients.get(&event.id) else {
+            continue;
+        };
+        for room in client.rooms.iter() {
+            if network_rooms.is_running(room) {
+                room_schedules
+                    .inboxes
+                    .entry(room.clone())
+                    .or_default()
+                    .push(event.clone());
+            }
+        }
+    }
+}
+fn run_room_schedules(world: &mut World) {
+    let network_rooms = world.resource::<NetworkRooms>();
+    let mut matches: Vec<String> = network_rooms.matches.iter().cloned().collect();
+    matches.sort();
+    let rooms: Vec<(String, Vec<u32>)> = matches
+        .into_iter()
+        .map(|room| {
+            let members = network_rooms.members(&room).collect();
+            (room, members)
+        })
+        .collect();
+    let mut room_schedules = world.resource_mut::<RoomSchedules>();
+    let stopped: HashSet<String> = room_schedules
+        .running
+        .iter()
+        .filter(|room| !rooms.iter().any(|(running, _)| running == *room))
+        .cloned()
+        .collect();
+    room_schedules.running = rooms.iter().map(|(room, _)| room.clone()).collect();
+    room_schedules
+        .inboxes
+        .retain(|room, _| !stopped.contains(room));
+    let schedules = room_schedules.schedules.clone();
+    if !stopped.is_empty() {
+        let despawned: Vec<Entity> = world
+            .query::<(Entity, &InRoom)>()
+            .iter(world)
+            .filter(|(_, in_room)| stopped.contains(&in_room.0))
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in despawned {
+            if let Some(entity) = world.get_entity_mut(entity) {
+                entity.despawn_recursive();
+            }
+        }
+    }
+    for (name, members) in rooms {
+        let messages = world
+            .resource_mut::<RoomSchedules>()
+            .inboxes
+            .remove(&name)
+            .unwrap_or_default();
+        world.insert_resource(CurrentRoom {
+            name,
+            members,
+            messages,
+        });
+        for schedule in schedules.iter() {
+            world.run_schedule(*schedule);
+        }
+    }
+    world.remove_resource::<CurrentRoom>();
+}
+fn remove_left_clients(
+    mut player_intergress_reader: EventReader<PlayerIntergressEvent>,
+    mut network_rooms: ResMut<NetworkRooms>,
+) {
+    for event in player_intergress_reader.read() {
+        if let IntergressType::Left = event.intergress_type {
+            network_rooms.leave_all(event.id);
+            network_rooms.clients.remove(&event.id);
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use crate::{
+    has_client_config, has_server_config, ClientDataReadEvent, ClientDataUploader,
+    ConnectionChange, Identify, IntergressType, NetworkEntityMap, NetworkRooms,
+    PlayerIntergressEvent, Recipient, RelevantEntities, ServerConnectionChangeEvent,
+    ServerDataReadEvent, ServerDataUploader, ServerStreams, RPC_IDENTIFIER,
+};
+use bevy::{
+    ecs::{event::ManualEventReader, system::SystemId},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+type HandleFn = fn(&mut World, u32, &[u8]) -> Option<bincode::Result<Vec<u8>>>;
+type ClientHandleFn = fn(&mut World, &[u8]) -> Option<bincode::Result<Vec<u8>>>;
+pub trait Rpc: Serialize + DeserializeOwned + Send + Sync + 'static {
+    type Response: Serialize + DeserializeOwned + Send + Sync + 'static;
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RequestId(u32);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RpcError {
+    Timeout,
+    Disconnected,
+    Unhandled,
+    InvalidResponse,
+}
+#[derive(Resource)]
+pub struct RpcConfig {
+    pub timeout: f32,
+}
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig { timeout: 5. }
+    }
+}
+#[derive(Event, Debug)]
+pub struct RpcResponseEvent<R: Rpc> {
+    pub request_id: RequestId,
+    pub response: Result<R::Response, RpcError>,
+}
+#[derive(Event, Debug)]
+pub struct RpcRepliesEvent<R: Rpc> {
+    pub request_id: RequestId,
+    pub replies: HashMap<u32, Result<R::Response, RpcError>>,
+}
+#[derive(Resource)]
+pub struct RpcClient<R: Rpc> {
+    index: u16,
+    next_id: u32,
+    queued: Vec<(u32, R, Option<f32>)>,
+    pending: HashMap<u32, f64>,
+}
+impl<R: Rpc> RpcClient<R> {
+    pub fn call(&mut self, request: R) -> RequestId {
+        self.queue(request, None)
+    }
+    pub fn call_with_timeout(&mut self, request: R, timeout: f32) -> RequestId {
+        self.queue(request, Some(timeout))
+    }
+    pub fn is_pending(&self, request_id: RequestId) -> bool {
+        self.pending.contains_key(&request_id.0)
+            || self.queued.iter().any(|(id, ..)| *id == request_id.0)
+    }
+    fn queue(&mut self, request: R, timeout: Option<f32>) -> RequestId {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.queued.push((id, request, timeout));
+        RequestId(id)
+    }
+}
+#[derive(Resource)]
+pub struct RpcServer<R: Rpc> {
+    index: u16,
+    next_id: u32,
+    queued: Vec<QueuedCall<R>>,
+    pending: HashMap<u32, PendingCall<R::Response>>,
+}
+impl<R: Rpc> RpcServer<R> {
+    pub fn send(&mut self, request: R, recipient: Recipient) {
+        self.queued.push(QueuedCall {
+            id: None,
+            request,
+            recipient,
+            timeout: None,
+        });
+    }
+    pub fn call(&mut self, request: R, recipient: Recipient) -> RequestId {
+        self.queue(request, recipient, None)
+    }
+    pub fn call_with_timeout(
+        &mut self,
+        request: R,
+        recipient: Recipient,
+        timeout: f32,
+    ) -> RequestId {
+        self.queue(request, recipient, Some(timeout))
+    }
+    pub fn is_pending(&self, request_id: RequestId) -> bool {
+        self.pending.contains_key(&request_id.0)
+            || self
+                .queued
+                .iter()
+                .any(|queued_call| queued_call.id == Some(request_id.0))
+    }
+    fn queue(&mut self, request: R, recipient: Recipient, timeout: Option<f32>) -> RequestId {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.queued.push(QueuedCall {
+            id: Some(id),
+            request,
+            recipient,
+            timeout,
+        });
+        RequestId(id)
+    }
+}
+struct QueuedCall<R> {
+    id: Option<u32>,
+    request: R,
+    recipient: Recipient,
+    timeout: Option<f32>,
+}
+struct PendingCall<T> {
+    deadline: f64,
+    waiting: HashSet<u32>,
+    replies: HashMap<u32, Result<T, RpcError>>,
+}
+#[derive(Resource)]
+struct RpcHandler<R: Rpc>(SystemId<(u32, R), R::Response>);
+#[derive(Resource)]
+struct ClientRpcHandler<R: Rpc>(SystemId<R, R::Response>);
+#[derive(Resource, Default)]
+struct RpcRegistry {
+    rpcs: Vec<RpcFns>,
+}
+struct RpcFns {
+    handle: HandleFn,
+    handle_client: ClientHandleFn,
+    respond: fn(&mut World, u32, Option<&[u8]>),
+    reply: fn(&mut World, u32, u32, Option<&[u8]>),
+}
+#[derive(Serialize, Deserialize, Debug)]
+enum RpcMessage {
+    Request {
+        index: u16,
+        id: Option<u32>,
+        bytes: Vec<u8>,
+    },
+    Response {
+        index: u16,
+        id: u32,
+        bytes: Option<Vec<u8>>,
+    },
+}
+impl Identify for RpcMessage {
+    fn get_identifier(&self) -> u32 {
+        RPC_IDENTIFIER
+    }
+}
+pub trait RpcApp {
+    fn rpc<R: Rpc>(&mut self) -> &mut Self;
+    fn rpc_handler<R: Rpc, M>(
+        &mut self,
+        handler: impl IntoSystem<(u32, R), R::Response, M> + 'static,
+    ) -> &mut Self;
+    fn client_rpc_handler<R: Rpc, M>(
+        &mut self,
+        handler: impl IntoSystem<R, R::Response, M> + 'static,
+    ) -> &mut Self;
+}
+impl RpcApp for App {
+    fn rpc<R: Rpc>(&mut self) -> &mut Self {
+        if !self.world().contains_resource::<RpcRegistry>() {
+            self.init_resource::<RpcRegistry>()
+                .init_resource::<RpcConfig>()
+                .add_systems(
+                    PreUpdate,
+                    (
+                        receive_server_messages.run_if(has_client_config),
+                        receive_client_messages.run_if(has_server_config),
+                    ),
+                );
+        }
+        let mut registry = self.world_mut().resource_mut::<RpcRegistry>();
+        let index = registry.rpcs.len() as u16;
+        registry.rpcs.push(RpcFns {
+            handle: handle_request::<R>,
+            handle_client: handle_call::<R>,
+            respond: receive_response::<R>,
+            reply: receive_reply::<R>,
+        });
+        self.insert_resource(RpcClient::<R> {
+            index,
+            next_id: 0,
+            queued: vec![],
+            pending: HashMap::new(),
+        })
+        .insert_resource(RpcServer::<R> {
+            index,
+            next_id: 0,
+            queued: vec![],
+            pending: HashMap::new(),
+        })
+        .add_event::<RpcResponseEvent<R>>()
+        .add_event::<RpcRepliesEvent<R>>()
+        .add_systems(
+            PostUpdate,
+            (
+                send_requests::<R>.run_if(has_client_config),
+                send_calls::<R>.run_if(has_server_config),
+            ),
+        )
+    }
+    fn rpc_handler<R: Rpc, M>(
+        &mut self,
+        handler: impl IntoSystem<(u32, R), R::Response, M> + 'static,
+    ) -> &mut Self {
+        let system_id = self.world_mut().register_system(handler);
+        self.insert_resource(RpcHandler(system_id))
+    }
+    fn client_rpc_handler<R: Rpc, M>(
+        &mut self,
+        handler: impl IntoSystem<R, R::Response, M> + 'static,
+    ) -> &mut Self {
+        let system_id = self.world_mut().register_system(handler);
+        self.insert_resource(ClientRpcHandler(system_id))
+    }
+}
+fn handle_request<R: Rpc>(
+    world: &mut World,
+    id: u32,
+    bytes: &[u8],
+) -> Option<bincode::Result<Vec<u8>>> {
+    let system_id = world.get_resource::<RpcHandler<R>>()?.0;
+    let request: R = match world.resource::<NetworkEntityMap>().deserialize(bytes) {
+        Ok(request) => request,
+        Err(err) => return Some(Err(err)),
+    };
+    let response = world.run_system_with_input(system_id, (id, request)).ok()?;
+    Some(bincode::serialize(&response))
+}
+fn handle_call<R: Rpc>(world: &mut World, bytes: &[u8]) -> Option<bincode::Result<Vec<u8>>> {
+    let system_id = world.get_resource::<ClientRpcHandler<R>>()?.0;
+    let network_entity_map = world.resource::<NetworkEntityMap>().clone();
+    let request: R = match network_entity_map.deserialize(bytes) {
+        Ok(request) => request,
+        Err(err) => return Some(Err(err)),
+    };
+    let response = world.run_system_with_input(system_id, request).ok()?;
+    Some(network_entity_map.scope(|| bincode::serialize(&response)))
+}
+fn receive_response<R: Rpc>(world: &mut World, id: u32, bytes: Option<&[u8]>) {
+    if world
+        .resource_mut::<RpcClient<R>>()
+        .pending
+        .remove(&id)
+        .is_none()
+    {
+        return;
+    }
+    let response = match bytes {
+        Some(bytes) => world
+            .resource::<NetworkEntityMap>()
+            .deserialize(bytes)
+            .map_err(|err| {
+                println!("Failed to deserialize an rpc response: {err:#?}");
+                RpcError::InvalidResponse
+            }),
+        None => Err(RpcError::Unhandled),
+    };
+    world.send_event(RpcResponseEvent::<R> {
+        request_id: RequestId(id),
+        response,
+    });
+}
+fn receive_reply<R: Rpc>(world: &mut World, client_id: u32, id: u32, bytes: Option<&[u8]>) {
+    let reply = match bytes {
+        Some(bytes) => world
+            .resource::<NetworkEntityMap>()
+            .deserialize(bytes)
+            .map_err(|err| {
+                println!("Failed to deserialize an rpc reply from id {client_id}: {err:#?}");
+                RpcError::InvalidResponse
+            }),
+        None => Err(RpcError::Unhandled),
+    };
+    let mut rpc_server = world.resource_mut::<RpcServer<R>>();
+    let Some(pending_call) = rpc_server.pending.get_mut(&id) else {
+        return;
+    };
+    if pending_call.waiting.remove(&client_id) {
+        pending_call.replies.insert(client_id, reply);
+    }
+}
+fn send_requests<R: Rpc>(
+    mut rpc_client: ResMut<RpcClient<R>>,
+    mut server_connection_change_reader: EventReader<ServerConnectionChangeEvent>,
+    mut rpc_response_writer: EventWriter<RpcResponseEvent<R>>,
+    mut client_data_uploader: ResMut<ClientDataUploader>,
+    rpc_config: Res<RpcConfig>,
+    time: Res<Time<Real>>,
+) {
+    let rpc_client = rpc_client.as_mut();
+    let now = time.elapsed_seconds_f64();
+    let disconnected = server_connection_change_reader
+        .read()
+        .any(|event| matches!(event.connection_change, ConnectionChange::Disconnected));
+    let mut failed: Vec<(u32, RpcError)> = match disconnected {
+        true => rpc_client
+            .pending
+            .drain()
+            .map(|(id, _)| (id, RpcError::Disconnected))
+            .collect(),
+        false => vec![],
+    };
+    rpc_client.pending.retain(|id, deadline| {
+        if *deadline > now {
+            return true;
+        }
+        failed.push((*id, RpcError::Timeout));
+        false
+    });
+    let network_entity_map = client_data_uploader.network_entity_map.clone();
+    for (id, request, timeout) in rpc_client.queued.drain(..) {
+        let bytes = match network_entity_map.scope(|| bincode::serialize(&request)) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                println!("Failed to serialize an rpc request: {err:#?}");
+                continue;
+            }
+        };
+        let uploaded = client_data_uploader.upload(RpcMessage::Request {
+            index: rpc_client.index,
+            id: Some(id),
+            bytes,
+        });
+        match uploaded {
+            Ok(()) => {
+                let timeout = timeout.unwrap_or(rpc_config.timeout);
+                rpc_client.pending.insert(id, now + timeout as f64);
+            }
+            Err(_) => failed.push((id, RpcError::Disconnected)),
+        }
+    }
+    rpc_response_writer.send_batch(failed.into_iter().map(|(id, err)| RpcResponseEvent {
+        request_id: RequestId(id),
+        response: Err(err),
+    }));
+}
+#[allow(clippy::too_many_arguments)]
+fn send_calls<R: Rpc>(
+    mut rpc_server: ResMut<RpcServer<R>>,
+    mut player_intergress_reader: EventReader<PlayerIntergressEvent>,
+    mut rpc_replies_writer: EventWriter<RpcRepliesEvent<R>>,
+    mut server_data_uploader: ResMut<ServerDataUploader>,
+    server_streams: Res<ServerStreams>,
+    relevant_entities: Res<RelevantEntities>,
+    network_rooms: Res<NetworkRooms>,
+    rpc_config: Res<RpcConfig>,
+    time: Res<Time<Real>>,
+) {
+    let rpc_server = rpc_server.as_mut();
+    let now = time.elapsed_seconds_f64();
+    for event in player_intergress_reader.read() {
+        let IntergressType::Left = event.intergress_type else {
+            continue;
+        };
+        for pending_call in rpc_server.pending.values_mut() {
+            if pending_call.waiting.remove(&event.id) {
+                pending_call
+                    .replies
+                    .insert(event.id, Err(RpcError::Disconnected));
+            }
+        }
+    }
+    for pending_call in rpc_server.pending.values_mut() {
+        if pending_call.deadline > now {
+            continue;
+        }
+        for id in pending_call.waiting.drain() {
+            pending_call.replies.insert(id, Err(RpcError::Timeout));
+        }
+    }
+    let clients: Vec<u32> = server_streams
+        .streams
+        .lock()
+        .unwrap()
+        .keys()
+        .copied()
+        .collect();
+    for queued_call in rpc_server.queued.drain(..) {
+        let bytes = match bincode::serialize(&queued_call.request) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                println!("Failed to serialize an rpc request: {err:#?}");
+                continue;
+            }
+        };
+        if let Some(id) = queued_call.id {
+            let timeout = queued_call.timeout.unwrap_or(rpc_config.timeout);
+            rpc_server.pending.insert(
+                id,
+                PendingCall {
+                    deadline: now + timeout as f64,
+                    waiting: clients
+                        .iter()
+                        .copied()
+                        .filter(|id| {
+                            queued_call
+                                .recipient
+                                .includes(*id, &relevant_entities, &network_rooms)
+                        })
+                        .collect(),
+                    replies: HashMap::new(),
+                },
+            );
+        }
+        server_data_uploader.upload(
+            RpcMessage::Request {
+                index: rpc_server.index,
+                id: queued_call.id,
+                bytes,
+            },
+            queued_call.recipient,
+        );
+    }
+    let completed: Vec<u32> = rpc_server
+        .pending
+        .iter()
+        .filter(|(_, pending_call)| pending_call.waiting.is_empty())
+        .map(|(id, _)| *id)
+        .collect();
+    for id in completed {
+        let Some(pending_call) = rpc_server.pending.remove(&id) else {
+            continue;
+        };
+        rpc_replies_writer.send(RpcRepliesEvent {
+            request_id: RequestId(id),
+            replies: pending_call.replies,
+        });
+    }
+}
+fn receive_server_messages(
+    world: &mut World,
+    mut client_data_read_reader: Local<ManualEventReader<ClientDataReadEvent>>,
+) {
+    let messages: Vec<RpcMessage> = client_data_read_reader
+        .read(world.resource::<Events<ClientDataReadEvent>>())
+        .filter(|event| event.data_packet.identifier == RPC_IDENTIFIER)
+        .filter_map(|event| bincode::deserialize(&event.data_packet.bytes).ok())
+        .collect();
+    world.resource_scope(|world, registry: Mut<RpcRegistry>| {
+        for message in messages {
+            match message {
+                RpcMessage::Request { index, id, bytes } => {
+                    let response = registry
+                        .rpcs
+                        .get(index as usize)
+                        .and_then(|rpc_fns| (rpc_fns.handle_client)(world, &bytes));
+                    let Some(id) = id else {
+                        continue;
+                    };
+                    let bytes = match response {
+                        Some(Ok(bytes)) => Some(bytes),
+                        Some(Err(err)) => {
+                            println!("Failed to handle an rpc request from the server: {err:#?}");
+                            None
+                        }
+                        None => None,
+                    };
+                    let _ = world
+                        .resource_mut::<ClientDataUploader>()
+                        .upload(RpcMessage::Response { index, id, bytes });
+                }
+                RpcMessage::Response { index, id, bytes } => {
+                    let Some(rpc_fns) = registry.rpcs.get(index as usize) else {
+                        continue;
+                    };
+                    (rpc_fns.respond)(world, id, bytes.as_deref());
+                }
+            }
+        }
+    });
+}
+fn receive_client_messages(
+    world: &mut World,
+    mut server_data_read_reader: Local<ManualEventReader<ServerDataReadEvent>>,
+) {
+    let messages: Vec<(u32, RpcMessage)> = server_data_read_reader
+        .read(world.resource::<Events<ServerDataReadEvent>>())
+        .filter(|event| event.data_packet.identifier == RPC_IDENTIFIER)
+        .filter_map(
+            |event| match bincode::deserialize(&event.data_packet.bytes) {
+                Ok(message) => Some((event.id, message)),
+                Err(err) => {
+                    println!(
+                        "Failed to deserialize an rpc message from id {}: {err:#?}",
+                        event.id
+                    );
+                    None
+                }
+            },
+        )
+        .collect();
+    world.resource_scope(|world, registry: Mut<RpcRegistry>| {
+        for (client_id, message) in messages {
+            match message {
+                RpcMessage::Request { index, id, bytes } => {
+                    let response = registry
+                        .rpcs
+                        .get(index as usize)
+                        .and_then(|rpc_fns| (rpc_fns.handle)(world, client_id, &bytes));
+                    let Some(id) = id else {
+                        continue;
+                    };
+                    let bytes = match response {
+                        Some(Ok(bytes)) => Some(bytes),
+                        Some(Err(err)) => {
+                            println!(
+                                "Failed to handle an rpc request from id {client_id}: {err:#?}"
+                            );
+                            None
+                        }
+                        None => None,
+                    };
+                    world.resource_mut::<ServerDataUploader>().upload(
+                        RpcMessage::Response { index, id, bytes },
+                        Recipient::Single { id: client_id },
+                    );
+                }
+                RpcMessage::Response { index, id, bytes } => {
+                    let Some(rpc_fns) = registry.rpcs.get(index as usize) else {
+                        continue;
+                    };
+                    (rpc_fns.reply)(world, client_id, id, bytes.as_deref());
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,114 @@
+use crate::{ClientStream, ServerStreams};
+use bevy::prelude::*;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    runtime::{Builder, Handle, Runtime},
+    task::AbortHandle,
+};
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+#[derive(Resource)]
+pub enum NetworkRuntimeConfig {
+    Owned { worker_threads: usize },
+    Handle(Handle),
+}
+impl Default for NetworkRuntimeConfig {
+    fn default() -> Self {
+        NetworkRuntimeConfig::Owned { worker_threads: 2 }
+    }
+}
+#[derive(Resource)]
+pub struct NetworkRuntime {
+    runtime: Option<Runtime>,
+    tasks: NetworkTasks,
+}
+#[derive(Clone)]
+pub(crate) struct NetworkTasks {
+    handle: Handle,
+    abort_handles: Arc<Mutex<Vec<AbortHandle>>>,
+}
+impl NetworkRuntime {
+    pub fn handle(&self) -> &Handle {
+        &self.tasks.handle
+    }
+    pub(crate) fn tasks(&self) -> NetworkTasks {
+        self.tasks.clone()
+    }
+    pub(crate) fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(future);
+    }
+    fn shutdown(&mut self) {
+        self.tasks.abort_all();
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+        }
+    }
+}
+impl NetworkTasks {
+    pub(crate) fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut abort_handles = self.abort_handles.lock().unwrap();
+        abort_handles.retain(|abort_handle| !abort_handle.is_finished());
+        abort_handles.push(self.handle.spawn(future).abort_handle());
+    }
+    fn abort_all(&self) {
+        for abort_handle in self.abort_handles.lock().unwrap().drain(..) {
+            abort_handle.abort();
+        }
+    }
+}
+pub(crate) fn runtime_setup(
+    mut commands: Commands,
+    network_runtime_config: Option<Res<NetworkRuntimeConfig>>,
+) {
+    let default_config = NetworkRuntimeConfig::default();
+    let (runtime, handle) = match network_runtime_config.as_deref().unwrap_or(&default_config) {
+        NetworkRuntimeConfig::Owned { worker_threads } => build_runtime(*worker_threads),
+        NetworkRuntimeConfig::Handle(handle) => (None, handle.clone()),
+    };
+    commands.insert_resource(NetworkRuntime {
+        runtime,
+        tasks: NetworkTasks {
+            handle,
+            abort_handles: Arc::new(Mutex::new(vec![])),
+        },
+    });
+}
+fn build_runtime(worker_threads: usize) -> (Option<Runtime>, Handle) {
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .thread_name("bevy_crab_networking")
+        .enable_all()
+        .build()
+        .expect("Failed to build the networking runtime");
+    let handle = runtime.handle().clone();
+    (Some(runtime), handle)
+}
+pub(crate) fn shutdown_runtime(
+    mut app_exit_reader: EventReader<AppExit>,
+    network_runtime: Option<ResMut<NetworkRuntime>>,
+    client_stream: Option<Res<ClientStream>>,
+    server_streams: Option<Res<ServerStreams>>,
+) {
+    if app_exit_reader.read().next().is_none() {
+        return;
+    }
+    if let Some(mut network_runtime) = network_runtime {
+        println!("Shutting down the networking runtime...");
+        network_runtime.shutdown();
+    }
+    if let Some(client_stream) = client_stream {
+        *client_stream.stream.lock().unwrap() = None;
+    }
+    if let Some(server_streams) = server_streams {
+        server_streams.streams.lock().unwrap().clear();
+    }
+}
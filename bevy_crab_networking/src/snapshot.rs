@@ -0,0 +1,365 @@
+use crate::{Identify, NetworkEntity, REPLICATION_ACK_IDENTIFIER, REPLICATION_IDENTIFIER};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+#[derive(Default, Clone, PartialEq, Debug)]
+pub(crate) struct Snapshot {
+    pub(crate) entities: BTreeMap<NetworkEntity, BTreeMap<u16, Bytes>>,
+}
+pub(crate) struct SnapshotHistory {
+    snapshots: VecDeque<(u32, Arc<Snapshot>)>,
+    next_id: u32,
+    capacity: usize,
+}
+impl SnapshotHistory {
+    pub(crate) fn new(capacity: usize) -> SnapshotHistory {
+        SnapshotHistory {
+            snapshots: VecDeque::new(),
+            next_id: 0,
+            capacity: capacity.max(1),
+        }
+    }
+    pub(crate) fn latest(&self) -> Option<(u32, &Arc<Snapshot>)> {
+        self.snapshots.back().map(|(id, snapshot)| (*id, snapshot))
+    }
+    pub(crate) fn get(&self, id: u32) -> Option<&Arc<Snapshot>> {
+        self.snapshots
+            .iter()
+            .find(|(snapshot_id, _)| *snapshot_id == id)
+            .map(|(_, snapshot)| snapshot)
+    }
+    pub(crate) fn push(&mut self, snapshot: Snapshot) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.snapshots.push_back((id, Arc::new(snapshot)));
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+        id
+    }
+    pub(crate) fn reserve_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        id
+    }
+    pub(crate) fn retain_from(&mut self, id: u32) {
+        while self.snapshots.len() > 1
+            && self.snapshots.front().map(|(front, _)| *front) != Some(id)
+        {
+            self.snapshots.pop_front();
+        }
+    }
+    pub(crate) fn insert(&mut self, id: u32, snapshot: Arc<Snapshot>) {
+        self.snapshots.push_back((id, snapshot));
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+    }
+    pub(crate) fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub(crate) struct SnapshotTime {
+    pub(crate) time: f64,
+    pub(crate) previous_time: f64,
+}
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct SnapshotMessage {
+    pub(crate) id: u32,
+    pub(crate) baseline: Option<u32>,
+    pub(crate) time: SnapshotTime,
+    pub(crate) server_time: f64,
+    pub(crate) spawns: Vec<NetworkEntity>,
+    pub(crate) despawns: Vec<NetworkEntity>,
+    pub(crate) components: Vec<(NetworkEntity, u16, ComponentDelta)>,
+    pub(crate) removals: Vec<(NetworkEntity, u16)>,
+}
+impl Identify for SnapshotMessage {
+    fn get_identifier(&self) -> u32 {
+        REPLICATION_IDENTIFIER
+    }
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) enum ComponentDelta {
+    Full(Vec<u8>),
+    Diff(Vec<u8>),
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct SnapshotAck(pub(crate) Option<u32>);
+impl Identify for SnapshotAck {
+    fn get_identifier(&self) -> u32 {
+        REPLICATION_ACK_IDENTIFIER
+    }
+}
+impl SnapshotMessage {
+    pub(crate) fn new(
+        id: u32,
+        time: SnapshotTime,
+        baseline: Option<(u32, &Snapshot)>,
+        current: &Snapshot,
+    ) -> Self {
+        let empty = Snapshot::default();
+        let (baseline_id, baseline) = match baseline {
+            Some((baseline_id, baseline)) => (Some(baseline_id), baseline),
+            None => (None, &empty),
+        };
+        let mut message = SnapshotMessage {
+            id,
+            baseline: baseline_id,
+            time,
+            ..Default::default()
+        };
+        for (network_entity, components) in current.entities.iter() {
+            let baseline_components = baseline.entities.get(network_entity);
+            if baseline_components.is_none() {
+                message.spawns.push(*network_entity);
+            }
+            for (component_index, bytes) in components.iter() {
+                let delta = match baseline_components.and_then(|c| c.get(component_index)) {
+                    Some(baseline_bytes) if baseline_bytes == bytes => continue,
+                    Some(baseline_bytes) => {
+                        let diff = diff(baseline_bytes, bytes);
+                        if diff.len() < bytes.len() {
+                            ComponentDelta::Diff(diff)
+                        } else {
+                            ComponentDelta::Full(bytes.to_vec())
+                        }
+                    }
+                    None => ComponentDelta::Full(bytes.to_vec()),
+                };
+                message
+                    .components
+                    .push((*network_entity, *component_index, delta));
+            }
+            if let Some(baseline_components) = baseline_components {
+                for component_index in baseline_components.keys() {
+                    if !components.contains_key(component_index) {
+                        message.removals.push((*network_entity, *component_index));
+                    }
+                }
+            }
+        }
+        for network_entity in baseline.entities.keys() {
+            if !current.entities.contains_key(network_entity) {
+                message.despawns.push(*network_entity);
+            }
+        }
+        message
+    }
+    pub(crate) fn apply(self, baseline: &Snapshot) -> Option<Snapshot> {
+        let mut snapshot = baseline.clone();
+        for network_entity in self.despawns {
+            snapshot.entities.remove(&network_entity);
+        }
+        for network_entity in self.spawns {
+            snapshot.entities.entry(network_entity).or_default();
+        }
+        for (network_entity, component_index) in self.removals {
+            if let Some(components) = snapshot.entities.get_mut(&network_entity) {
+                components.remove(&component_index);
+            }
+        }
+        for (network_entity, component_index, delta) in self.components {
+            let components = snapshot.entities.get_mut(&network_entity)?;
+            let bytes = match delta {
+                ComponentDelta::Full(bytes) => bytes,
+                ComponentDelta::Diff(diff) => {
+                    let baseline_bytes = components.get(&component_index)?;
+                    patch(baseline_bytes, &diff)?
+                }
+            };
+            components.insert(component_index, bytes.into());
+        }
+        Some(snapshot)
+    }
+}
+fn diff(baseline: &[u8], current: &[u8]) -> Vec<u8> {
+    let mut diff = vec![];
+    write_varint(&mut diff, current.len());
+    let xor = |i: usize| current[i] ^ baseline.get(i).copied().unwrap_or(0);
+    let mut i = 0;
+    while i < current.len() {
+        let zeros_start = i;
+        while i < current.len() && xor(i) == 0 {
+            i += 1;
+        }
+        let literal_start = i;
+        while i < current.len() && xor(i) != 0 {
+            i += 1;
+        }
+        write_varint(&mut diff, literal_start - zeros_start);
+        write_varint(&mut diff, i - literal_start);
+        diff.extend((literal_start..i).map(xor));
+    }
+    diff
+}
+fn patch(baseline: &[u8], diff: &[u8]) -> Option<Vec<u8>> {
+    let mut diff = diff;
+    let len = read_varint(&mut diff)?;
+    let mut current: Vec<u8> = (0..len)
+        .map(|i| baseline.get(i).copied().unwrap_or(0))
+        .collect();
+    let mut i: usize = 0;
+    while !diff.is_empty() {
+        i = i.checked_add(read_varint(&mut diff)?)?;
+        let literal_len = read_varint(&mut diff)?;
+        if literal_len > diff.len() || i.checked_add(literal_len)? > len {
+            return None;
+        }
+        for (byte, xor) in current[i..i + literal_len].iter_mut().zip(diff) {
+            *byte ^= xor;
+        }
+        diff = &diff[literal_len..];
+        i += literal_len;
+    }
+    Some(current)
+}
+fn write_varint(buffer: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+fn read_varint(buffer: &mut &[u8]) -> Option<usize> {
+    let mut value = 0usize;
+    for shift in (0..usize::BITS).step_by(7) {
+        let (byte, rest) = buffer.split_first()?;
+        *buffer = rest;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    type EntityComponents<'a> = (u64, &'a [(u16, &'a [u8])]);
+    fn snapshot(entities: &[EntityComponents]) -> Snapshot {
+        Snapshot {
+            entities: entities
+                .iter()
+                .map(|(entity, components)| {
+                    let components = components
+                        .iter()
+                        .map(|(index, bytes)| (*index, Bytes::copy_from_slice(bytes)))
+                        .collect();
+                    (NetworkEntity(*entity), components)
+                })
+                .collect(),
+        }
+    }
+    #[test]
+    fn diff_patch_round_trip() {
+        let cases: [(&[u8], &[u8]); 7] = [
+            (&[], &[]),
+            (&[1, 2, 3], &[1, 2, 3]),
+            (&[1, 2, 3, 4, 5, 6], &[1, 9, 3, 4, 8, 6]),
+            (&[1, 2, 3], &[1, 2, 3, 4, 5]),
+            (&[1, 2, 3, 4, 5], &[1, 7]),
+            (&[], &[0, 0, 5]),
+            (&[0xff; 300], &[0; 300]),
+        ];
+        for (baseline, current) in cases {
+            let diff = diff(baseline, current);
+            assert_eq!(patch(baseline, &diff).as_deref(), Some(current));
+        }
+        let mut long_baseline = vec![0u8; 1000];
+        long_baseline[500] = 1;
+        let mut long_current = long_baseline.clone();
+        long_current[900] = 2;
+        let long_diff = diff(&long_baseline, &long_current);
+        assert!(long_diff.len() < 10);
+        assert_eq!(patch(&long_baseline, &long_diff), Some(long_current));
+    }
+    #[test]
+    fn patch_rejects_malformed_diffs() {
+        let baseline = [1, 2, 3];
+        let diff = diff(&baseline, &[1, 5, 3]);
+        assert_eq!(patch(&baseline, &diff[..diff.len() - 1]), None);
+        let mut out_of_bounds = vec![];
+        write_varint(&mut out_of_bounds, 3);
+        write_varint(&mut out_of_bounds, 2);
+        write_varint(&mut out_of_bounds, 2);
+        out_of_bounds.extend_from_slice(&[1, 1]);
+        assert_eq!(patch(&baseline, &out_of_bounds), None);
+        let mut overflowing = vec![];
+        write_varint(&mut overflowing, 3);
+        write_varint(&mut overflowing, usize::MAX);
+        write_varint(&mut overflowing, 1);
+        overflowing.push(1);
+        assert_eq!(patch(&baseline, &overflowing), None);
+    }
+    #[test]
+    fn varint_boundaries() {
+        let cases = [
+            (0, 1),
+            (0x7f, 1),
+            (0x80, 2),
+            (0x3fff, 2),
+            (0x4000, 3),
+            (u32::MAX as usize, 5),
+            (usize::MAX, 10),
+        ];
+        for (value, encoded_len) in cases {
+            let mut buffer = vec![];
+            write_varint(&mut buffer, value);
+            assert_eq!(buffer.len(), encoded_len, "{value}");
+            let mut reader = &buffer[..];
+            assert_eq!(read_varint(&mut reader), Some(value));
+            assert!(reader.is_empty());
+            let mut truncated = &buffer[..buffer.len() - 1];
+            assert_eq!(read_varint(&mut truncated), None);
+        }
+        let mut too_long = &[0x80; 11][..];
+        assert_eq!(read_varint(&mut too_long), None);
+    }
+    #[test]
+    fn apply_against_older_acked_baseline() {
+        let mut history = SnapshotHistory::new(8);
+        let acked = snapshot(&[
+            (1, &[(0, &[1, 2, 3, 4, 5, 6, 7, 8]), (1, &[9])]),
+            (2, &[(0, &[5])]),
+        ]);
+        let acked_id = history.push(acked.clone());
+        history.push(snapshot(&[
+            (1, &[(0, &[1, 2, 3, 4, 5, 6, 7, 0])]),
+            (2, &[(0, &[5])]),
+        ]));
+        let current = snapshot(&[
+            (1, &[(0, &[1, 2, 3, 4, 5, 6, 7, 9])]),
+            (3, &[(0, &[7, 7]), (2, &[])]),
+        ]);
+        let current_id = history.push(current.clone());
+        let baseline = history.get(acked_id).unwrap();
+        let message = SnapshotMessage::new(
+            current_id,
+            SnapshotTime::default(),
+            Some((acked_id, baseline)),
+            &current,
+        );
+        assert_eq!(message.baseline, Some(acked_id));
+        assert_eq!(message.spawns, vec![NetworkEntity(3)]);
+        assert_eq!(message.despawns, vec![NetworkEntity(2)]);
+        assert_eq!(message.removals, vec![(NetworkEntity(1), 1)]);
+        assert!(message.components.iter().any(|(entity, index, delta)| {
+            *entity == NetworkEntity(1) && *index == 0 && matches!(delta, ComponentDelta::Diff(_))
+        }));
+        let bytes = bincode::serialize(&message).unwrap();
+        let message: SnapshotMessage = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(message.apply(baseline), Some(current.clone()));
+        let full = SnapshotMessage::new(current_id, SnapshotTime::default(), None, &current);
+        assert_eq!(full.apply(&Snapshot::default()), Some(current.clone()));
+        let message = SnapshotMessage::new(
+            current_id,
+            SnapshotTime::default(),
+            Some((acked_id, &acked)),
+            &current,
+        );
+        assert_eq!(message.apply(&snapshot(&[(1, &[])])), None);
+    }
+}
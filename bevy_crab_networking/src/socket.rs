@@ -0,0 +1,90 @@
+use std::{
+    io::{Error, ErrorKind, Write},
+    net::TcpStream,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+#[derive(Clone)]
+pub struct SocketOptions {
+    pub nodelay: bool,
+    pub send_buffer_size: Option<usize>,
+    pub recv_buffer_size: Option<usize>,
+    pub max_pending_bytes: usize,
+    pub send_timeout: Duration,
+}
+impl Default for SocketOptions {
+    fn default() -> Self {
+        SocketOptions {
+            nodelay: true,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            max_pending_bytes: 4 * 1024 * 1024,
+            send_timeout: Duration::from_secs(5),
+        }
+    }
+}
+#[derive(Default)]
+pub(crate) struct PendingWrites {
+    bytes: Vec<u8>,
+    stalled_since: Option<Instant>,
+}
+pub(crate) fn apply_socket_options(
+    stream: &TcpStream,
+    socket_options: &SocketOptions,
+) -> std::io::Result<()> {
+    stream.set_nodelay(socket_options.nodelay)?;
+    let socket = socket2::SockRef::from(stream);
+    if let Some(send_buffer_size) = socket_options.send_buffer_size {
+        socket.set_send_buffer_size(send_buffer_size)?;
+    }
+    if let Some(recv_buffer_size) = socket_options.recv_buffer_size {
+        socket.set_recv_buffer_size(recv_buffer_size)?;
+    }
+    Ok(())
+}
+// The reading half of the stream is driven by tokio, which puts the shared socket in
+// non-blocking mode, so a full send buffer shows up here as WouldBlock. Whatever doesn't fit
+// is kept and retried on the next call, so a slow peer never stalls the thread that sends.
+pub(crate) fn write_pending(
+    mut stream: &TcpStream,
+    pending_writes: &Mutex<PendingWrites>,
+    bytes: &[u8],
+    socket_options: &SocketOptions,
+) -> std::io::Result<()> {
+    let mut pending_writes = pending_writes.lock().unwrap();
+    pending_writes.bytes.extend_from_slice(bytes);
+    let mut written = 0;
+    while written < pending_writes.bytes.len() {
+        match stream.write(&pending_writes.bytes[written..]) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(count) => written += count,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    pending_writes.bytes.drain(..written);
+    if pending_writes.bytes.is_empty() {
+        pending_writes.stalled_since = None;
+        return Ok(());
+    }
+    if written > 0 || pending_writes.stalled_since.is_none() {
+        pending_writes.stalled_since = Some(Instant::now());
+    }
+    if pending_writes.bytes.len() > socket_options.max_pending_bytes {
+        return Err(Error::new(
+            ErrorKind::OutOfMemory,
+            "the peer isn't reading fast enough and its pending writes went over max_pending_bytes",
+        ));
+    }
+    if pending_writes
+        .stalled_since
+        .is_some_and(|stalled_since| stalled_since.elapsed() > socket_options.send_timeout)
+    {
+        return Err(Error::new(
+            ErrorKind::TimedOut,
+            "the peer hasn't read anything for longer than send_timeout",
+        ));
+    }
+    Ok(())
+}
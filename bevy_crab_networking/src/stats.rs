@@ -0,0 +1,101 @@
+use bevy::prelude::*;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+#[derive(Resource, Clone, Default)]
+pub struct NetworkStats {
+    counters: Arc<NetworkCounters>,
+}
+#[derive(Default)]
+struct NetworkCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    packets_sent: AtomicU64,
+    packets_received: AtomicU64,
+    compressed_packets_sent: AtomicU64,
+    compressed_packets_received: AtomicU64,
+    uncompressed_bytes_sent: AtomicU64,
+    compressed_bytes_sent: AtomicU64,
+    uncompressed_bytes_received: AtomicU64,
+    compressed_bytes_received: AtomicU64,
+}
+impl NetworkStats {
+    pub fn bytes_sent(&self) -> u64 {
+        self.counters.bytes_sent.load(Ordering::Relaxed)
+    }
+    pub fn bytes_received(&self) -> u64 {
+        self.counters.bytes_received.load(Ordering::Relaxed)
+    }
+    pub fn packets_sent(&self) -> u64 {
+        self.counters.packets_sent.load(Ordering::Relaxed)
+    }
+    pub fn packets_received(&self) -> u64 {
+        self.counters.packets_received.load(Ordering::Relaxed)
+    }
+    pub fn compressed_packets_sent(&self) -> u64 {
+        self.counters
+            .compressed_packets_sent
+            .load(Ordering::Relaxed)
+    }
+    pub fn compressed_packets_received(&self) -> u64 {
+        self.counters
+            .compressed_packets_received
+            .load(Ordering::Relaxed)
+    }
+    pub fn sent_compression_ratio(&self) -> f32 {
+        compression_ratio(
+            &self.counters.uncompressed_bytes_sent,
+            &self.counters.compressed_bytes_sent,
+        )
+    }
+    pub fn received_compression_ratio(&self) -> f32 {
+        compression_ratio(
+            &self.counters.uncompressed_bytes_received,
+            &self.counters.compressed_bytes_received,
+        )
+    }
+    pub(crate) fn record_sent(&self, wire_bytes: usize, compression: Option<(usize, usize)>) {
+        self.counters
+            .bytes_sent
+            .fetch_add(wire_bytes as u64, Ordering::Relaxed);
+        self.counters.packets_sent.fetch_add(1, Ordering::Relaxed);
+        if let Some((uncompressed, compressed)) = compression {
+            self.counters
+                .compressed_packets_sent
+                .fetch_add(1, Ordering::Relaxed);
+            self.counters
+                .uncompressed_bytes_sent
+                .fetch_add(uncompressed as u64, Ordering::Relaxed);
+            self.counters
+                .compressed_bytes_sent
+                .fetch_add(compressed as u64, Ordering::Relaxed);
+        }
+    }
+    pub(crate) fn record_received(&self, wire_bytes: usize, compression: Option<(usize, usize)>) {
+        self.counters
+            .bytes_received
+            .fetch_add(wire_bytes as u64, Ordering::Relaxed);
+        self.counters
+            .packets_received
+            .fetch_add(1, Ordering::Relaxed);
+        if let Some((uncompressed, compressed)) = compression {
+            self.counters
+                .compressed_packets_received
+                .fetch_add(1, Ordering::Relaxed);
+            self.counters
+                .uncompressed_bytes_received
+                .fetch_add(uncompressed as u64, Ordering::Relaxed);
+            self.counters
+                .compressed_bytes_received
+                .fetch_add(compressed as u64, Ordering::Relaxed);
+        }
+    }
+}
+fn compression_ratio(uncompressed: &AtomicU64, compressed: &AtomicU64) -> f32 {
+    let compressed = compressed.load(Ordering::Relaxed);
+    if compressed == 0 {
+        return 1.;
+    }
+    uncompressed.load(Ordering::Relaxed) as f32 / compressed as f32
+}
@@ -0,0 +1,177 @@
+use crate::{
+    has_client_config, has_server_config, ClientDataReadEvent, ClientDataUploader,
+    ConnectionChange, Identify, Recipient, ServerConnectionChangeEvent, ServerDataReadEvent,
+    ServerDataUploader, TICK_SYNC_IDENTIFIER,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+const MAX_TICK_DRIFT: i32 = 8;
+const TIMESTEP_ADJUSTMENT: f64 = 0.02;
+const MAX_TIMESTEP_ADJUSTMENT: f64 = 0.1;
+#[derive(Resource)]
+pub struct NetworkTickConfig {
+    pub tick_rate: f64,
+    pub client_lead: u32,
+    pub sync_interval: u32,
+}
+impl Default for NetworkTickConfig {
+    fn default() -> Self {
+        NetworkTickConfig {
+            tick_rate: 60.,
+            client_lead: 2,
+            sync_interval: 30,
+        }
+    }
+}
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct NetworkTick {
+    tick: u32,
+}
+impl NetworkTick {
+    pub fn get(&self) -> u32 {
+        self.tick
+    }
+}
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum TickSet {
+    Advance,
+    Flush,
+}
+#[derive(Serialize, Deserialize, Debug)]
+enum TickSyncMessage {
+    Ping {
+        generation: u32,
+    },
+    Pong {
+        generation: u32,
+        client_tick: u32,
+        server_tick: u32,
+    },
+}
+impl Identify for TickSyncMessage {
+    fn get_identifier(&self) -> u32 {
+        TICK_SYNC_IDENTIFIER
+    }
+}
+#[derive(Resource, Default)]
+struct ClientTickSync {
+    synced: bool,
+    generation: u32,
+}
+pub(crate) struct TickPlugin;
+impl Plugin for TickPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkTickConfig>()
+            .init_resource::<NetworkTick>()
+            .init_resource::<ClientTickSync>()
+            .configure_sets(FixedFirst, TickSet::Advance)
+            .configure_sets(FixedPostUpdate, TickSet::Flush)
+            .add_systems(Startup, tick_setup)
+            .add_systems(FixedFirst, advance_tick.in_set(TickSet::Advance))
+            .add_systems(
+                FixedFirst,
+                send_tick_ping
+                    .after(TickSet::Advance)
+                    .run_if(has_client_config),
+            )
+            .add_systems(PreUpdate, receive_tick_sync.run_if(has_client_config))
+            .add_systems(PreUpdate, answer_tick_pings.run_if(has_server_config));
+    }
+}
+fn base_timestep(network_tick_config: &NetworkTickConfig) -> Duration {
+    Duration::from_secs_f64(1. / network_tick_config.tick_rate)
+}
+fn tick_setup(network_tick_config: Res<NetworkTickConfig>, mut time: ResMut<Time<Fixed>>) {
+    time.set_timestep(base_timestep(&network_tick_config));
+}
+fn advance_tick(mut network_tick: ResMut<NetworkTick>) {
+    network_tick.tick = network_tick.tick.wrapping_add(1);
+}
+fn send_tick_ping(
+    client_data_uploader: Option<ResMut<ClientDataUploader>>,
+    client_tick_sync: Res<ClientTickSync>,
+    network_tick: Res<NetworkTick>,
+    network_tick_config: Res<NetworkTickConfig>,
+) {
+    let Some(mut client_data_uploader) = client_data_uploader else {
+        return;
+    };
+    if !client_data_uploader.is_connected() {
+        return;
+    }
+    if client_tick_sync.synced && network_tick.tick % network_tick_config.sync_interval.max(1) != 0
+    {
+        return;
+    }
+    let _ = client_data_uploader.upload(TickSyncMessage::Ping {
+        generation: client_tick_sync.generation,
+    });
+}
+fn answer_tick_pings(
+    mut server_data_reader: EventReader<ServerDataReadEvent>,
+    mut server_data_uploader: ResMut<ServerDataUploader>,
+    network_tick: Res<NetworkTick>,
+) {
+    for event in server_data_reader.read() {
+        if event.data_packet.identifier != TICK_SYNC_IDENTIFIER {
+            continue;
+        }
+        if let Ok(TickSyncMessage::Ping { generation }) =
+            bincode::deserialize(&event.data_packet.bytes)
+        {
+            server_data_uploader.upload(
+                TickSyncMessage::Pong {
+                    generation,
+                    client_tick: event.tick,
+                    server_tick: network_tick.tick,
+                },
+                Recipient::Single { id: event.id },
+            );
+        }
+    }
+}
+fn receive_tick_sync(
+    mut client_data_reader: EventReader<ClientDataReadEvent>,
+    mut server_connection_change_reader: EventReader<ServerConnectionChangeEvent>,
+    mut client_tick_sync: ResMut<ClientTickSync>,
+    mut network_tick: ResMut<NetworkTick>,
+    mut time: ResMut<Time<Fixed>>,
+    network_tick_config: Res<NetworkTickConfig>,
+) {
+    let base_timestep = base_timestep(&network_tick_config);
+    for event in server_connection_change_reader.read() {
+        if let ConnectionChange::Disconnected = event.connection_change {
+            client_tick_sync.synced = false;
+            time.set_timestep(base_timestep);
+        }
+    }
+    for event in client_data_reader.read() {
+        if event.data_packet.identifier != TICK_SYNC_IDENTIFIER {
+            continue;
+        }
+        let Ok(TickSyncMessage::Pong {
+            generation,
+            client_tick,
+            server_tick,
+        }) = bincode::deserialize(&event.data_packet.bytes)
+        else {
+            continue;
+        };
+        if generation != client_tick_sync.generation {
+            continue;
+        }
+        let ahead = client_tick.wrapping_sub(server_tick) as i32;
+        let error = ahead - network_tick_config.client_lead as i32;
+        if !client_tick_sync.synced || error.abs() > MAX_TICK_DRIFT {
+            network_tick.tick = network_tick.tick.wrapping_sub(error as u32);
+            client_tick_sync.synced = true;
+            client_tick_sync.generation = client_tick_sync.generation.wrapping_add(1);
+            time.set_timestep(base_timestep);
+            continue;
+        }
+        let adjustment = (error as f64 * TIMESTEP_ADJUSTMENT)
+            .clamp(-MAX_TIMESTEP_ADJUSTMENT, MAX_TIMESTEP_ADJUSTMENT);
+        time.set_timestep(base_timestep.mul_f64(1. + adjustment));
+    }
+}
@@ -12,8 +12,9 @@ use bevy::{
     sprite::{MaterialMesh2dBundle, Mesh2dHandle},
 };
 use bevy_crab_networking::{
-    connect_to_server, disconnect_from_server, is_connected_to_server, AutoReconnect,
-    BevyCrabNetworkingPlugin, ClientConfig, ClientDataReadEvent, ClientDataUploader,
+    connect_to_server, disconnect_from_server, interpolate_transform, is_connected_to_server,
+    AutoReconnect, BevyCrabNetworkingPlugin, ClientConfig, ClientDataReadEvent, ClientDataUploader,
+    InterpolationApp, Replicated, ReplicationApp,
 };
 use bevy_crab_networking_example::{Packet, PlayerType, Players, DATA_UPLOAD_SPEED};
 #[derive(Event)]
@@ -31,14 +32,19 @@ struct MainText;
 struct HasChoice(bool);
 #[derive(Resource)]
 struct DataUploadTimer(Timer);
+type OtherPlayerFilter = (Without<MainPlayer>, Without<Replicated>);
 fn main() {
     App::new()
         .add_plugins((DefaultPlugins, BevyCrabNetworkingPlugin))
+        .replicate::<PlayerType>()
+        .replicate::<Transform>()
+        .interpolate::<Transform>(interpolate_transform)
         .add_event::<UpdateText>()
         .add_event::<SpawnPlayer>()
         .insert_resource(ClientConfig {
             server_address: "127.0.0.1:2942".parse().unwrap(),
             auto_reconnect: AutoReconnect::None,
+            ..Default::default()
         })
         .insert_resource(DataUploadTimer(Timer::from_seconds(
             1. / DATA_UPLOAD_SPEED,
@@ -50,7 +56,7 @@ fn main() {
             Update,
             (handle_incoming_data, handle_input, send_position).run_if(is_connected_to_server),
         )
-        .add_systems(Update, (update_text, spawn_players))
+        .add_systems(Update, (update_text, spawn_players, follow_other_player))
         .run();
 }
 fn setup(mut commands: Commands) {
@@ -100,15 +106,18 @@ fn spawn_players(
 ) {
     for event in spawn_player_reader.read() {
         let spawned_player = commands
-            .spawn(MaterialMesh2dBundle {
-                mesh: Mesh2dHandle(meshes.add(Rectangle::new(100.0, 100.0))),
-                material: materials.add(match event.player_type {
-                    PlayerType::Red => Color::from(RED),
-                    PlayerType::Yellow => Color::from(YELLOW),
-                }),
-                transform: Transform::from_xyz(0., 0., 0.),
-                ..default()
-            })
+            .spawn((
+                MaterialMesh2dBundle {
+                    mesh: Mesh2dHandle(meshes.add(Rectangle::new(100.0, 100.0))),
+                    material: materials.add(match event.player_type {
+                        PlayerType::Red => Color::from(RED),
+                        PlayerType::Yellow => Color::from(YELLOW),
+                    }),
+                    transform: Transform::from_xyz(0., 0., 0.),
+                    ..default()
+                },
+                event.player_type.clone(),
+            ))
             .with_children(|parent| {
                 parent.spawn(Text2dBundle {
                     text: Text::from_section(
@@ -214,7 +223,7 @@ fn handle_incoming_data(
     mut spawn_player_writer: EventWriter<SpawnPlayer>,
     mut has_choice: ResMut<HasChoice>,
     mut commands: Commands,
-    mut other_player: Query<(Entity, &Mesh2dHandle, &mut Transform), Without<MainPlayer>>,
+    other_player: Query<(Entity, &Mesh2dHandle), Without<MainPlayer>>,
     main_player: Query<(Entity, &Mesh2dHandle), With<MainPlayer>>,
 ) {
     for event in client_data_read_reader.read() {
@@ -297,13 +306,20 @@ fn handle_incoming_data(
                     Err(err) => println!("Getting other player failed: {err:#?}"),
                 };
             }
-            Packet::PlayerPosition(position) => {
-                if let Ok((_, _, mut other_player_transform)) = other_player.get_single_mut() {
-                    other_player_transform.translation.x = position.x;
-                    other_player_transform.translation.y = position.y;
-                }
-            }
             _ => {}
         }
     }
 }
+fn follow_other_player(
+    replicated_players: Query<(&Transform, &PlayerType), With<Replicated>>,
+    mut other_player: Query<(&mut Transform, &PlayerType), OtherPlayerFilter>,
+) {
+    for (mut other_player_transform, other_player_type) in other_player.iter_mut() {
+        for (transform, player_type) in replicated_players.iter() {
+            if player_type == other_player_type {
+                other_player_transform.translation.x = transform.translation.x;
+                other_player_transform.translation.y = transform.translation.y;
+            }
+        }
+    }
+}
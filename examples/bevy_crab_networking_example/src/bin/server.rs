@@ -8,7 +8,7 @@
 use bevy::{ecs::system::RunSystemOnce, prelude::*};
 use bevy_crab_networking::{
     host_server, BevyCrabNetworkingPlugin, IntergressType, PlayerIntergressEvent, Recipient,
-    ServerConfig, ServerDataReadEvent, ServerDataUploader,
+    Replicated, ReplicationApp, ServerConfig, ServerDataReadEvent, ServerDataUploader,
 };
 use bevy_crab_networking_example::{Packet, PlayerType, Players};
 #[derive(Event)]
@@ -26,9 +26,14 @@ fn main() {
     App::new()
         .add_plugins(MinimalPlugins)
         .add_plugins(BevyCrabNetworkingPlugin)
+        .replicate::<PlayerType>()
+        .replicate::<Transform>()
         .add_event::<SpawnPlayer>()
         .add_event::<DespawnPlayer>()
-        .insert_resource(ServerConfig { host_port: 2942 })
+        .insert_resource(ServerConfig {
+            host_port: 2942,
+            ..Default::default()
+        })
         .insert_resource(PlayerIds {
             yellow_player_id: None,
             red_player_id: None,
@@ -136,7 +141,12 @@ fn spawn_players(
     mut player_ids: ResMut<PlayerIds>,
 ) {
     for event in spawn_player_reader.read() {
-        commands.spawn((Transform::default(), event.0.clone(), PlayerId(event.1)));
+        commands.spawn((
+            Transform::default(),
+            event.0.clone(),
+            PlayerId(event.1),
+            Replicated,
+        ));
         match event.0 {
             PlayerType::Yellow => {
                 player_ids.yellow_player_id = Some(event.1);
@@ -151,7 +161,7 @@ fn handle_incoming_data(
     mut server_data_read_reader: EventReader<ServerDataReadEvent>,
     mut spawn_player_writer: EventWriter<SpawnPlayer>,
     mut data_uploader: ResMut<ServerDataUploader>,
-    players_query: Query<(&Transform, &PlayerType)>,
+    mut players_query: Query<(&mut Transform, &PlayerId)>,
 ) {
     for event in server_data_read_reader.read() {
         if event.data_packet.identifier != 0 {
@@ -173,10 +183,12 @@ fn handle_incoming_data(
                 );
             }
             Packet::PlayerPosition(position) => {
-                data_uploader.upload(
-                    Packet::PlayerPosition(position),
-                    Recipient::AllExcept { id: event.id },
-                );
+                for (mut transform, player_id) in players_query.iter_mut() {
+                    if player_id.0 == event.id {
+                        transform.translation.x = position.x;
+                        transform.translation.y = position.y;
+                    }
+                }
             }
             _ => {}
         }
@@ -2,7 +2,7 @@ use bevy::{math::Vec2, prelude::Component};
 use bevy_crab_networking::Identify;
 use serde::{Deserialize, Serialize};
 pub const DATA_UPLOAD_SPEED: f32 = 60.;
-#[derive(Component, Clone, Serialize, Deserialize, Debug)]
+#[derive(Component, Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub enum PlayerType {
     Yellow,
     Red,
@@ -0,0 +1,56 @@
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+use bevy_crab_networking::{
+    host_server, BevyCrabNetworkingPlugin, FromClient, NetworkRooms, Recipient, ServerConfig,
+    ToClients,
+};
+use bevy_crab_networking_lobby_example::{
+    LobbyExampleLibPlugin, MatchMessage, PresentTicket, GAME_SERVER_PORT, TICKET_MAX_AGE,
+    TICKET_SECRET,
+};
+struct GameServerPlugin;
+impl Plugin for GameServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ServerConfig {
+            host_port: GAME_SERVER_PORT,
+            ..Default::default()
+        })
+        .add_systems(Startup, setup)
+        .add_systems(Update, admit_players);
+    }
+}
+fn main() {
+    App::new()
+        .add_plugins((
+            MinimalPlugins,
+            BevyCrabNetworkingPlugin,
+            LobbyExampleLibPlugin,
+            GameServerPlugin,
+        ))
+        .run();
+}
+fn setup(world: &mut World) {
+    world.run_system_once(host_server).unwrap();
+}
+fn admit_players(
+    mut present_ticket_reader: EventReader<FromClient<PresentTicket>>,
+    mut network_rooms: ResMut<NetworkRooms>,
+    mut to_clients_writer: EventWriter<ToClients<MatchMessage>>,
+) {
+    for FromClient { id, event } in present_ticket_reader.read() {
+        let ticket = &event.0;
+        if !ticket.verify(&TICKET_SECRET, TICKET_MAX_AGE) {
+            println!("Rejected an invalid ticket from id: {id}");
+            continue;
+        }
+        let room = format!("lobby-{}", ticket.lobby.0);
+        network_rooms.join(room.clone(), *id);
+        network_rooms.start_match(room.clone());
+        let players = network_rooms.members(&room).count();
+        println!("Id: {id} joined {room}, {players} players in the match");
+        to_clients_writer.send(ToClients {
+            event: MatchMessage(format!("Player {id} joined {room}")),
+            recipient: Recipient::room(room),
+            target: None,
+        });
+    }
+}
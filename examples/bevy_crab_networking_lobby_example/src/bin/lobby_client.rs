@@ -0,0 +1,159 @@
+use bevy::{ecs::system::RunSystemOnce, prelude::*, utils::HashMap};
+use bevy_crab_networking::{
+    connect_to_server, disconnect_from_server, AutoReconnect, BevyCrabNetworkingPlugin,
+    ClientConfig, ConnectionChange, LobbyEvent, LobbyRequest, LobbyResponse, LobbyTicket,
+    LocalClientId, RpcClient, RpcResponseEvent, ServerConnectionChangeEvent, ToServer,
+};
+use bevy_crab_networking_lobby_example::{
+    LobbyExampleLibPlugin, MatchMessage, PresentTicket, LOBBY_SERVER_PORT, PLAYERS_PER_MATCH,
+};
+#[derive(Resource, Default)]
+struct Ticket(Option<LobbyTicket>);
+struct LobbyClientPlugin;
+impl Plugin for LobbyClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ClientConfig {
+            server_address: format!("127.0.0.1:{LOBBY_SERVER_PORT}").parse().unwrap(),
+            auto_reconnect: AutoReconnect::None,
+            ..Default::default()
+        })
+        .init_resource::<Ticket>()
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                handle_connection_changes,
+                handle_lobby_responses,
+                handle_lobby_events,
+                handle_match_messages,
+            ),
+        );
+    }
+}
+fn main() {
+    App::new()
+        .add_plugins((
+            MinimalPlugins,
+            BevyCrabNetworkingPlugin,
+            LobbyExampleLibPlugin,
+            LobbyClientPlugin,
+        ))
+        .run();
+}
+fn setup(mut commands: Commands) {
+    commands.add(|w: &mut World| {
+        if let Err(err) = w.run_system_once(connect_to_server) {
+            eprintln!("Couldn't connect to the lobby server: {err:#?}");
+        }
+    });
+}
+fn handle_connection_changes(
+    mut commands: Commands,
+    mut server_connection_change_reader: EventReader<ServerConnectionChangeEvent>,
+    mut lobby_rpc: ResMut<RpcClient<LobbyRequest>>,
+    mut to_server_writer: EventWriter<ToServer<PresentTicket>>,
+    ticket: Res<Ticket>,
+) {
+    for event in server_connection_change_reader.read() {
+        match (&event.connection_change, &ticket.0) {
+            (ConnectionChange::Connected, None) => {
+                lobby_rpc.call(LobbyRequest::List);
+            }
+            (ConnectionChange::Connected, Some(ticket)) => {
+                to_server_writer.send(ToServer {
+                    event: PresentTicket(ticket.clone()),
+                    target: None,
+                });
+            }
+            (ConnectionChange::Disconnected, Some(_)) => {
+                commands.add(|w: &mut World| {
+                    if let Err(err) = w.run_system_once(connect_to_server) {
+                        eprintln!("Couldn't connect to the game server: {err:#?}");
+                    }
+                });
+            }
+            (ConnectionChange::Disconnected, None) => {
+                println!("Disconnected from the lobby server");
+            }
+        }
+    }
+}
+fn handle_lobby_responses(
+    mut lobby_response_reader: EventReader<RpcResponseEvent<LobbyRequest>>,
+    mut lobby_rpc: ResMut<RpcClient<LobbyRequest>>,
+) {
+    for event in lobby_response_reader.read() {
+        match &event.response {
+            Ok(Ok(LobbyResponse::Lobbies(lobbies))) => {
+                match lobbies.iter().find(|lobby| !lobby.is_full()) {
+                    Some(lobby) => {
+                        println!("Joining lobby \"{}\"", lobby.name);
+                        lobby_rpc.call(LobbyRequest::Join { lobby: lobby.id });
+                    }
+                    None => {
+                        println!("No open lobbies, creating one");
+                        lobby_rpc.call(LobbyRequest::Create {
+                            name: "Crab duel".to_string(),
+                            slots: PLAYERS_PER_MATCH,
+                            metadata: HashMap::from([("map".to_string(), "beach".to_string())]),
+                        });
+                    }
+                }
+            }
+            Ok(Ok(LobbyResponse::Joined(lobby))) => {
+                println!(
+                    "Joined lobby \"{}\" ({}/{} players)",
+                    lobby.name,
+                    lobby.players.len(),
+                    lobby.slots
+                );
+                lobby_rpc.call(LobbyRequest::SetReady { ready: true });
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => println!("Lobby request failed: {err:?}"),
+            Err(err) => println!("Lobby request failed: {err:?}"),
+        }
+    }
+}
+fn handle_lobby_events(
+    mut commands: Commands,
+    mut lobby_event_reader: EventReader<LobbyEvent>,
+    mut lobby_rpc: ResMut<RpcClient<LobbyRequest>>,
+    mut client_config: ResMut<ClientConfig>,
+    mut ticket: ResMut<Ticket>,
+    local_client_id: Option<Res<LocalClientId>>,
+) {
+    for event in lobby_event_reader.read() {
+        match event {
+            LobbyEvent::Updated(lobby) => {
+                let ready = lobby.players.iter().filter(|player| player.ready).count();
+                println!(
+                    "Lobby \"{}\": {}/{} players, {ready} ready",
+                    lobby.name,
+                    lobby.players.len(),
+                    lobby.slots
+                );
+                let is_host = local_client_id.as_ref().map(|id| id.get()) == Some(lobby.host);
+                if is_host && lobby.is_full() && lobby.all_ready() {
+                    lobby_rpc.call(LobbyRequest::Start);
+                }
+            }
+            LobbyEvent::Started {
+                address,
+                ticket: lobby_ticket,
+            } => {
+                println!("Lobby started, moving to the game server at {address}");
+                client_config.server_address = *address;
+                ticket.0 = Some(lobby_ticket.clone());
+                commands.add(|w: &mut World| {
+                    let _ = w.run_system_once(disconnect_from_server);
+                });
+            }
+        }
+    }
+}
+fn handle_match_messages(mut match_message_reader: EventReader<MatchMessage>) {
+    for event in match_message_reader.read() {
+        println!("Game server: {}", event.0);
+    }
+}
@@ -0,0 +1,46 @@
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+use bevy_crab_networking::{
+    host_server, BevyCrabNetworkingPlugin, LobbyConfig, LobbyStarted, ServerConfig,
+};
+use bevy_crab_networking_lobby_example::{
+    LobbyExampleLibPlugin, GAME_SERVER_PORT, LOBBY_SERVER_PORT, TICKET_SECRET,
+};
+struct LobbyServerPlugin;
+impl Plugin for LobbyServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ServerConfig {
+            host_port: LOBBY_SERVER_PORT,
+            ..Default::default()
+        })
+        .insert_resource(LobbyConfig {
+            game_server_address: format!("127.0.0.1:{GAME_SERVER_PORT}").parse().unwrap(),
+            secret: TICKET_SECRET,
+            require_ready: true,
+        })
+        .add_systems(Startup, setup)
+        .add_systems(Update, log_started_lobbies);
+    }
+}
+fn main() {
+    App::new()
+        .add_plugins((
+            MinimalPlugins,
+            BevyCrabNetworkingPlugin,
+            LobbyExampleLibPlugin,
+            LobbyServerPlugin,
+        ))
+        .run();
+}
+fn setup(world: &mut World) {
+    world.run_system_once(host_server).unwrap();
+}
+fn log_started_lobbies(mut lobby_started_reader: EventReader<LobbyStarted>) {
+    for event in lobby_started_reader.read() {
+        println!(
+            "Lobby \"{}\" started with {} players, sending them to {}",
+            event.lobby.name,
+            event.lobby.players.len(),
+            event.address
+        );
+    }
+}
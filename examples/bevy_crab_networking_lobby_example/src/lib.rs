@@ -0,0 +1,21 @@
+use bevy::prelude::*;
+use bevy_crab_networking::{LobbyPlugin, LobbyTicket, NetworkDirection, NetworkEventApp};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+pub const LOBBY_SERVER_PORT: u16 = 46393;
+pub const GAME_SERVER_PORT: u16 = 46394;
+pub const PLAYERS_PER_MATCH: u32 = 2;
+pub const TICKET_SECRET: [u8; 32] = *b"change me before going to prod!!";
+pub const TICKET_MAX_AGE: Duration = Duration::from_secs(30);
+#[derive(Event, Serialize, Deserialize, Clone, Debug)]
+pub struct PresentTicket(pub LobbyTicket);
+#[derive(Event, Serialize, Deserialize, Clone, Debug)]
+pub struct MatchMessage(pub String);
+pub struct LobbyExampleLibPlugin;
+impl Plugin for LobbyExampleLibPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(LobbyPlugin)
+            .add_network_event::<PresentTicket>(NetworkDirection::ClientToServer)
+            .add_network_event::<MatchMessage>(NetworkDirection::ServerToClient);
+    }
+}